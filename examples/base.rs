@@ -1,8 +1,8 @@
 use libappindicator_zbus::{
     tray,
     utils::{
-        ButtonOptions, Category, EventUpdate, MenuStatus, MenuTree, MenuUnit, RadioGroupBuilder,
-        RadioOptions, TextDirection, ToggleState, ToggleType,
+        ButtonOptions, Category, CheckboxOptions, EventUpdate, ItemOptions, MenuStatus, MenuTree,
+        MenuUnit, RadioGroupBuilder, RadioOptions, TextDirection, ToggleState, ToggleType,
     },
 };
 use zbus::fdo::Result;
@@ -56,21 +56,25 @@ impl Menu {
         let group = RadioGroupBuilder::new()
             .append(
                 RadioOptions {
-                    label: "abc".to_owned(),
-                    enabled: true,
+                    item: ItemOptions {
+                        label: "abc".to_owned(),
+                        enabled: true,
+                        ..Default::default()
+                    },
                     toggle_type: ToggleType::Checkmark,
                     toggle_state: ToggleState::Selected,
-                    ..Default::default()
                 },
                 Message::Toggled(1),
             )
             .append(
                 RadioOptions {
-                    label: "efg".to_owned(),
-                    enabled: true,
+                    item: ItemOptions {
+                        label: "efg".to_owned(),
+                        enabled: true,
+                        ..Default::default()
+                    },
                     toggle_type: ToggleType::Checkmark,
                     toggle_state: ToggleState::UnSelected,
-                    ..Default::default()
                 },
                 Message::Toggled(2),
             );
@@ -80,26 +84,46 @@ impl Menu {
                     label: "Hello".to_owned(),
                     enabled: true,
                     icon_name: "nheko".to_owned(),
+                    shortcut: vec![vec!["Control".to_owned(), "h".to_owned()]],
+                    ..Default::default()
                 },
                 Message::Clicked,
             ))
+            .push(MenuUnit::checkbox(
+                CheckboxOptions {
+                    item: ItemOptions {
+                        label: "Mute".to_owned(),
+                        enabled: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Message::Clicked,
+            ))
+            .push(MenuUnit::separator())
             .push(MenuUnit::radio_group(group))
             .push(MenuUnit::button(
                 ButtonOptions {
                     label: "World".to_owned(),
                     icon_name: "fcitx_pinyin".to_owned(),
                     enabled: true,
+                    ..Default::default()
                 },
                 Message::Clicked,
             ))
-            .push(MenuUnit::sub_menu("Next".to_owned()).push(MenuUnit::button(
-                ButtonOptions {
-                    label: "Good".to_owned(),
-                    enabled: true,
-                    icon_name: "wezterm".to_owned(),
-                },
-                Message::Clicked,
-            )))
+            .push(
+                MenuUnit::sub_menu("Next".to_owned())
+                    .with_key("next")
+                    .push(MenuUnit::button(
+                        ButtonOptions {
+                            label: "Good".to_owned(),
+                            enabled: true,
+                            icon_name: "wezterm".to_owned(),
+                            ..Default::default()
+                        },
+                        Message::Clicked,
+                    )),
+            )
     }
 
     fn status(&self) -> MenuStatus {
@@ -112,21 +136,9 @@ impl Menu {
         forward_message: Message,
         _timestamp: u32,
     ) -> EventUpdate {
-        if let MenuUnit::RadioGroup { selections } = button {
-            for selection in selections.iter_mut() {
-                let MenuUnit::RadioButton {
-                    options, message, ..
-                } = selection
-                else {
-                    continue;
-                };
-                options.toggle_state = ToggleState::UnSelected;
-                if forward_message == *message {
-                    options.toggle_state = ToggleState::Selected;
-                }
-            }
-            self.revision += 1;
-            return EventUpdate::UpdateCurrent;
+        if let Some(selected) = button.selected() {
+            println!("selected: {selected:?}");
+            return EventUpdate::None;
         }
         println!("message: {button:?}, {forward_message:?}");
         EventUpdate::None
@@ -0,0 +1,15 @@
+use libappindicator_zbus::host;
+
+#[tokio::main]
+async fn main() {
+    let connection = host()
+        .with_on_event(|event| println!("{event:?}"))
+        .run()
+        .await
+        .unwrap();
+
+    for item in connection.items() {
+        println!("{}: {} ({:?})", item.service, item.title, item.status);
+    }
+    std::future::pending::<()>().await;
+}
@@ -1,8 +1,8 @@
 use libappindicator_zbus::{
     tray,
     utils::{
-        ButtonOptions, Category, EventUpdate, IconPixmap, MenuStatus, MenuTree, MenuUnit,
-        RadioGroupBuilder, RadioOptions,
+        ButtonOptions, Category, EventUpdate, IconPixmap, ItemOptions, MenuStatus, MenuTree,
+        MenuUnit, RadioGroupBuilder, RadioOptions,
     },
 };
 use zbus::fdo::Result;
@@ -10,18 +10,16 @@ use zbus::fdo::Result;
 const IMAGE_DATA: &[u8] = include_bytes!("../misc/logo.png");
 
 struct Base {
-    pixmap: IconPixmap,
+    pixmaps: Vec<IconPixmap>,
 }
 
 impl Base {
     fn boot() -> Self {
-        let data = image::load_from_memory(IMAGE_DATA).unwrap();
-        let pixmap = IconPixmap {
-            width: data.width() as i32,
-            height: data.height() as i32,
-            data: data.as_bytes().to_vec(),
-        };
-        Self { pixmap }
+        let data = image::load_from_memory(IMAGE_DATA).unwrap().to_rgba8();
+        let pixmap = IconPixmap::from_rgba(data.width(), data.height(), data.as_raw()).unwrap();
+        Self {
+            pixmaps: pixmap.sizes(),
+        }
     }
 
     fn activate(&mut self, _x: i32, _y: i32) -> Result<()> {
@@ -39,7 +37,7 @@ impl Base {
         Ok(())
     }
     fn icon_pixmap(&self) -> Result<Vec<IconPixmap>> {
-        Ok(vec![self.pixmap.clone()])
+        Ok(self.pixmaps.clone())
     }
 }
 
@@ -66,16 +64,22 @@ impl Menu {
         let group = RadioGroupBuilder::new()
             .append(
                 RadioOptions {
-                    label: "A".to_owned(),
-                    enabled: true,
+                    item: ItemOptions {
+                        label: "A".to_owned(),
+                        enabled: true,
+                        ..Default::default()
+                    },
                     ..Default::default()
                 },
                 Message::Toggled,
             )
             .append(
                 RadioOptions {
-                    label: "B".to_owned(),
-                    enabled: true,
+                    item: ItemOptions {
+                        label: "B".to_owned(),
+                        enabled: true,
+                        ..Default::default()
+                    },
                     ..Default::default()
                 },
                 Message::Toggled,
@@ -86,6 +90,7 @@ impl Menu {
                     label: "Hello".to_owned(),
                     enabled: true,
                     icon_name: "nheko".to_owned(),
+                    ..Default::default()
                 },
                 Message::Clicked,
             ))
@@ -94,6 +99,7 @@ impl Menu {
                     label: "World".to_owned(),
                     icon_name: "fcitx_pinyin".to_owned(),
                     enabled: true,
+                    ..Default::default()
                 },
                 Message::Toggled,
             ))
@@ -0,0 +1,9 @@
+use libappindicator_zbus::watcher;
+
+#[tokio::main]
+async fn main() {
+    let connection = watcher().await.unwrap();
+
+    println!("{:?}", connection.unique_name());
+    std::future::pending::<()>().await;
+}
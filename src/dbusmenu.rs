@@ -19,48 +19,248 @@
 //!
 //! [Writing a client proxy]: https://dbus2.github.io/zbus/client.html
 //! [D-Bus standard interfaces]: https://dbus.freedesktop.org/doc/dbus-specification.html#standard-interfaces,
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::ops::Deref;
+use std::sync::Arc;
 use std::sync::atomic::{self, AtomicI32};
 
 use serde::{Deserialize, Serialize};
-use zbus::zvariant::{self, OwnedValue, Type, Value, as_value::optional};
+use zbus::zvariant::{self, OwnedValue, Str, Type, Value, as_value::optional};
 
 use zbus::{interface, object_server::SignalEmitter};
 
+pub mod client;
+mod diff;
+
+use client::MenuLayout;
+use diff::{MenuChanges, MenuSnapshot};
 pub mod event_types;
 
 pub use event_types::*;
 
-#[derive(Type, Debug, Serialize, Deserialize, Default, OwnedValue, Value, Clone)]
+#[derive(Type, Debug, Serialize, Deserialize, Default, OwnedValue, Value, Clone, PartialEq)]
 /// Specified options for a [`Screencast::create_session`] request.
 #[zvariant(signature = "dict")]
 pub struct MenuProperty {
+    #[serde(
+        rename = "type",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[zvariant(rename = "type")]
+    pub item_type: Option<MenuItemType>,
     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
     pub label: Option<String>,
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        rename = "icon-name",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     #[zvariant(rename = "icon-name")]
     pub icon_name: Option<String>,
     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
     pub enabled: Option<bool>,
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        rename = "toggle-type",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     #[zvariant(rename = "toggle-type")]
     pub toggle_type: Option<ToggleType>,
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        rename = "toggle-state",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     #[zvariant(rename = "toggle-state")]
     pub toggle_state: Option<ToggleState>,
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        rename = "children-display",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     #[zvariant(rename = "children-display")]
     pub children_display: Option<String>,
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub visible: Option<bool>,
+    #[serde(
+        rename = "icon-data",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[zvariant(rename = "icon-data")]
+    pub icon_data: Option<Vec<u8>>,
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub shortcut: Option<Vec<Vec<String>>>,
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub disposition: Option<Disposition>,
+    #[serde(
+        rename = "accessible-desc",
+        with = "optional",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[zvariant(rename = "accessible-desc")]
+    pub accessible_desc: Option<String>,
 }
 
 impl MenuProperty {
     /// Key name
+    pub const TYPE: &str = "type";
     pub const LABEL: &str = "label";
     pub const ICON_NAME: &str = "icon-name";
     pub const ENABLED: &str = "enabled";
     pub const TOGGLE_TYPE: &str = "toggle-type";
     pub const TOGGLE_STATE: &str = "toggle-state";
     pub const CHILDREN_DISPLAY: &str = "children-display";
+    pub const VISIBLE: &str = "visible";
+    pub const ICON_DATA: &str = "icon-data";
+    pub const SHORTCUT: &str = "shortcut";
+    pub const DISPOSITION: &str = "disposition";
+    pub const ACCESSIBLE_DESC: &str = "accessible-desc";
+
+    pub const NAMES: &[&str] = &[
+        Self::TYPE,
+        Self::LABEL,
+        Self::ICON_NAME,
+        Self::ENABLED,
+        Self::TOGGLE_TYPE,
+        Self::TOGGLE_STATE,
+        Self::CHILDREN_DISPLAY,
+        Self::VISIBLE,
+        Self::ICON_DATA,
+        Self::SHORTCUT,
+        Self::DISPOSITION,
+        Self::ACCESSIBLE_DESC,
+    ];
+}
+
+impl MenuProperty {
+    /// Override every property that is set in `other`.
+    pub fn merge(&mut self, other: MenuProperty) {
+        let MenuProperty {
+            item_type,
+            label,
+            icon_name,
+            enabled,
+            toggle_type,
+            toggle_state,
+            children_display,
+            visible,
+            icon_data,
+            shortcut,
+            disposition,
+            accessible_desc,
+        } = other;
+        if item_type.is_some() {
+            self.item_type = item_type;
+        }
+        if label.is_some() {
+            self.label = label;
+        }
+        if icon_name.is_some() {
+            self.icon_name = icon_name;
+        }
+        if enabled.is_some() {
+            self.enabled = enabled;
+        }
+        if toggle_type.is_some() {
+            self.toggle_type = toggle_type;
+        }
+        if toggle_state.is_some() {
+            self.toggle_state = toggle_state;
+        }
+        if children_display.is_some() {
+            self.children_display = children_display;
+        }
+        if visible.is_some() {
+            self.visible = visible;
+        }
+        if icon_data.is_some() {
+            self.icon_data = icon_data;
+        }
+        if shortcut.is_some() {
+            self.shortcut = shortcut;
+        }
+        if disposition.is_some() {
+            self.disposition = disposition;
+        }
+        if accessible_desc.is_some() {
+            self.accessible_desc = accessible_desc;
+        }
+    }
+
+    /// Keep only the properties listed in `names`, or all of them when empty.
+    pub fn filter(&self, names: &[&str]) -> MenuProperty {
+        let mut output = self.clone();
+        if names.is_empty() {
+            return output;
+        }
+        for name in Self::NAMES {
+            if !names.contains(name) {
+                output.remove(name);
+            }
+        }
+        output
+    }
+
+    /// The value of the property called `name`, if it is set.
+    pub fn get(&self, name: &str) -> Option<OwnedValue> {
+        match name {
+            Self::TYPE => self.item_type.and_then(|value| value.try_into().ok()),
+            Self::LABEL => self.label.as_deref().map(|label| Str::from(label).into()),
+            Self::ICON_NAME => self.icon_name.as_deref().map(|icon| Str::from(icon).into()),
+            Self::ENABLED => self.enabled.map(OwnedValue::from),
+            Self::TOGGLE_TYPE => self.toggle_type.and_then(|value| value.try_into().ok()),
+            Self::TOGGLE_STATE => self.toggle_state.and_then(|value| value.try_into().ok()),
+            Self::CHILDREN_DISPLAY => self
+                .children_display
+                .as_deref()
+                .map(|display| Str::from(display).into()),
+            Self::VISIBLE => self.visible.map(OwnedValue::from),
+            Self::ICON_DATA => self
+                .icon_data
+                .clone()
+                .and_then(|data| Value::from(data).try_into().ok()),
+            Self::SHORTCUT => self
+                .shortcut
+                .clone()
+                .and_then(|shortcut| Value::from(shortcut).try_into().ok()),
+            Self::DISPOSITION => self.disposition.and_then(|value| value.try_into().ok()),
+            Self::ACCESSIBLE_DESC => self
+                .accessible_desc
+                .as_deref()
+                .map(|desc| Str::from(desc).into()),
+            _ => None,
+        }
+    }
+
+    /// Unset the property called `name`, returning whether it was known.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match name {
+            Self::TYPE => self.item_type = None,
+            Self::LABEL => self.label = None,
+            Self::ICON_NAME => self.icon_name = None,
+            Self::ENABLED => self.enabled = None,
+            Self::TOGGLE_TYPE => self.toggle_type = None,
+            Self::TOGGLE_STATE => self.toggle_state = None,
+            Self::CHILDREN_DISPLAY => self.children_display = None,
+            Self::VISIBLE => self.visible = None,
+            Self::ICON_DATA => self.icon_data = None,
+            Self::SHORTCUT => self.shortcut = None,
+            Self::DISPOSITION => self.disposition = None,
+            Self::ACCESSIBLE_DESC => self.accessible_desc = None,
+            _ => return false,
+        }
+        true
+    }
 }
 
 impl MenuProperty {
@@ -102,6 +302,201 @@ impl Deref for Id {
     }
 }
 
+/// A user chosen identity for a [`MenuUnit`], used to keep its id the
+/// same when the menu is rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MenuKey {
+    Named(String),
+    Hashed(u64),
+}
+
+impl MenuKey {
+    /// Creates a key from any hashable value.
+    pub fn hashed(value: impl Hash) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        MenuKey::Hashed(hasher.finish())
+    }
+}
+
+impl From<&str> for MenuKey {
+    fn from(value: &str) -> Self {
+        MenuKey::Named(value.to_owned())
+    }
+}
+
+impl From<String> for MenuKey {
+    fn from(value: String) -> Self {
+        MenuKey::Named(value)
+    }
+}
+
+/// Hands out the ids of one menu, reusing them for keyed units.
+#[derive(Debug)]
+pub(crate) struct IdAllocator {
+    next: i32,
+    keys: HashMap<MenuKey, Id>,
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        IdAllocator {
+            next: *Id::MAIN + 1,
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl IdAllocator {
+    /// An id not in `taken`, which it is added to. Once every id was handed
+    /// out, the count starts over, skipping the ones still in use.
+    fn fresh(&mut self, taken: &mut HashSet<i32>) -> Id {
+        loop {
+            let id = self.next;
+            self.next = self.next.checked_add(1).unwrap_or(*Id::MAIN + 1);
+            if taken.insert(id) {
+                return Id(id);
+            }
+        }
+    }
+
+    /// Give every unit of `tree` its id, dropping the keys no longer used.
+    ///
+    /// The ids of `previous`, the tree it replaces, are not handed out to
+    /// new units, as they may be given back with [`reuse_ids`].
+    pub(crate) fn assign<Message: Clone>(
+        &mut self,
+        previous: &MenuTree<Message>,
+        tree: &mut MenuTree<Message>,
+    ) {
+        let mut seen = HashSet::new();
+        let mut taken = HashSet::new();
+        collect_ids(previous.get_unit(), &mut taken);
+        self.assign_unit(tree.get_unit_mut(), &mut seen, &mut taken);
+        self.keys.retain(|key, _| seen.contains(key));
+    }
+
+    /// Give ids to `units`, about to replace `previous` in `tree`.
+    pub(crate) fn assign_new<Message: Clone>(
+        &mut self,
+        tree: &MenuTree<Message>,
+        previous: &[MenuUnit<Message>],
+        units: &mut [MenuUnit<Message>],
+    ) {
+        let mut seen = HashSet::new();
+        collect_keys(tree.get_unit(), &mut seen);
+        let mut taken = HashSet::new();
+        collect_ids(tree.get_unit(), &mut taken);
+        for unit in previous {
+            collect_ids(unit, &mut taken);
+        }
+        for unit in units {
+            self.assign_unit(unit, &mut seen, &mut taken);
+        }
+    }
+
+    fn assign_unit<Message: Clone>(
+        &mut self,
+        unit: &mut MenuUnit<Message>,
+        seen: &mut HashSet<MenuKey>,
+        taken: &mut HashSet<i32>,
+    ) {
+        match unit {
+            MenuUnit::Root { sub_menus } => {
+                for sub_menu in sub_menus {
+                    self.assign_unit(sub_menu, seen, taken);
+                }
+            }
+            MenuUnit::RadioGroup { selections } => {
+                for selection in selections {
+                    self.assign_unit(selection, seen, taken);
+                }
+            }
+            MenuUnit::SubMenu { id, key, .. }
+            | MenuUnit::StandardButton { id, key, .. }
+            | MenuUnit::RadioButton { id, key, .. }
+            | MenuUnit::Checkbox { id, key, .. }
+            | MenuUnit::Separator { id, key } => {
+                *id = match key {
+                    // A key used twice in one tree only keeps its id once
+                    Some(key) if seen.insert(key.clone()) => match self.keys.get(key) {
+                        Some(id) => *id,
+                        None => {
+                            let id = self.fresh(taken);
+                            self.keys.insert(key.clone(), id);
+                            id
+                        }
+                    },
+                    _ => self.fresh(taken),
+                };
+                if let Some(sub_menus) = unit.sub_menus_mut() {
+                    for sub_menu in sub_menus {
+                        self.assign_unit(sub_menu, seen, taken);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Give unkeyed units the id of the unit of the same kind they replace at the
+/// same position, so rebuilding identical children is not a layout change.
+fn reuse_ids<Message: Clone>(previous: &[MenuUnit<Message>], units: &mut [MenuUnit<Message>]) {
+    for (previous, unit) in previous.iter().zip(units) {
+        if std::mem::discriminant(previous) != std::mem::discriminant(unit)
+            || previous.key().is_some()
+            || unit.key().is_some()
+        {
+            continue;
+        }
+        match (previous, unit) {
+            (
+                MenuUnit::RadioGroup {
+                    selections: previous,
+                },
+                MenuUnit::RadioGroup { selections },
+            ) => reuse_ids(previous, selections),
+            (previous, unit) => {
+                if let (IdOrGroup::Id(previous_id), Some(id)) =
+                    (previous.id_or_ids(), unit.id_mut())
+                {
+                    *id = previous_id;
+                }
+                if let (Some(previous), Some(sub_menus)) =
+                    (previous.sub_menus(), unit.sub_menus_mut())
+                {
+                    reuse_ids(previous, sub_menus);
+                }
+            }
+        }
+    }
+}
+
+fn collect_keys<Message: Clone>(unit: &MenuUnit<Message>, keys: &mut HashSet<MenuKey>) {
+    if let Some(key) = unit.key() {
+        keys.insert(key.clone());
+    }
+    let children = match unit {
+        MenuUnit::RadioGroup { selections } => Some(selections),
+        _ => unit.sub_menus(),
+    };
+    for child in children.into_iter().flatten() {
+        collect_keys(child, keys);
+    }
+}
+
+fn collect_ids<Message: Clone>(unit: &MenuUnit<Message>, ids: &mut HashSet<i32>) {
+    match unit.id_or_ids() {
+        IdOrGroup::Id(id) => {
+            ids.insert(*id);
+        }
+        IdOrGroup::Groups(group) => ids.extend(group.iter().map(|id| **id)),
+    }
+    for child in unit.sub_menus().into_iter().flatten() {
+        collect_ids(child, ids);
+    }
+}
+
 #[allow(unused)]
 #[derive(Debug, Clone)]
 pub enum MenuType {
@@ -109,12 +504,15 @@ pub enum MenuType {
     SubMenu,
     Button,
     RadioGroup,
+    Checkbox,
+    Separator,
 }
 
 #[derive(Debug, Clone)]
 pub enum MenuUnit<Message: Clone> {
     StandardButton {
         id: Id,
+        key: Option<MenuKey>,
         options: ButtonOptions,
         message: Message,
     },
@@ -123,21 +521,36 @@ pub enum MenuUnit<Message: Clone> {
     },
     SubMenu {
         id: Id,
-        label: String,
+        key: Option<MenuKey>,
+        options: SubMenuOptions,
         sub_menus: Vec<MenuUnit<Message>>,
+        /// Set for submenus filled by [`crate::Tray::with_populate_sub_menu`].
+        dynamic: Option<Message>,
     },
     RadioGroup {
         selections: Vec<MenuUnit<Message>>,
     },
     RadioButton {
         id: Id,
+        key: Option<MenuKey>,
         options: RadioOptions,
         message: Message,
     },
+    Checkbox {
+        id: Id,
+        key: Option<MenuKey>,
+        options: CheckboxOptions,
+        message: Message,
+    },
+    Separator {
+        id: Id,
+        key: Option<MenuKey>,
+    },
 }
 
 #[derive(Debug, Clone)]
 struct RadioButtonBuilder<Message: Clone> {
+    key: Option<MenuKey>,
     options: RadioOptions,
     message: Message,
 }
@@ -147,6 +560,12 @@ pub struct RadioGroupBuilder<Message: Clone> {
     buttons: Vec<RadioButtonBuilder<Message>>,
 }
 
+impl<Message: Clone> Default for RadioGroupBuilder<Message> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<Message: Clone> RadioGroupBuilder<Message> {
     pub fn new() -> Self {
         Self {
@@ -155,16 +574,41 @@ impl<Message: Clone> RadioGroupBuilder<Message> {
     }
 
     pub fn append(mut self, options: RadioOptions, message: Message) -> Self {
-        self.buttons.push(RadioButtonBuilder { options, message });
+        self.buttons.push(RadioButtonBuilder {
+            key: None,
+            options,
+            message,
+        });
+        self
+    }
+
+    /// Append a button whose id is kept across menu rebuilds.
+    pub fn append_with_key(
+        mut self,
+        key: impl Into<MenuKey>,
+        options: RadioOptions,
+        message: Message,
+    ) -> Self {
+        self.buttons.push(RadioButtonBuilder {
+            key: Some(key.into()),
+            options,
+            message,
+        });
         self
     }
 
     fn build(self) -> MenuUnit<Message> {
         let mut selections = vec![];
 
-        for RadioButtonBuilder { options, message } in self.buttons {
+        for RadioButtonBuilder {
+            key,
+            options,
+            message,
+        } in self.buttons
+        {
             selections.push(MenuUnit::RadioButton {
                 id: Id::unique(),
+                key,
                 options,
                 message,
             });
@@ -203,22 +647,86 @@ impl<Message: Clone> From<&MenuTree<Message>> for MenuItem {
         (&value.0).into()
     }
 }
+/// What every kind of menu item shows, on its own for buttons and submenus
+/// and inside the options of the toggles.
 #[derive(Debug, Clone, Default)]
-pub struct ButtonOptions {
+pub struct ItemOptions {
     pub label: String,
     pub enabled: bool,
+    /// Sent as the `visible` property, so items are shown unless set.
+    pub hidden: bool,
     pub icon_name: String,
+    /// PNG data of the icon, for when `icon_name` is not enough.
+    pub icon_data: Vec<u8>,
+    /// Key combinations hinted next to the label, like `[["Control", "q"]]`.
+    pub shortcut: Vec<Vec<String>>,
+    pub disposition: Disposition,
+    pub accessible_desc: String,
+}
+
+impl ItemOptions {
+    /// `visible` and `disposition` are only sent when they differ from the
+    /// defaults of the spec.
+    fn property(&self) -> MenuProperty {
+        MenuProperty {
+            label: Some(self.label.clone()),
+            icon_name: non_empty_str(&self.icon_name),
+            enabled: Some(self.enabled),
+            visible: self.hidden.then_some(false),
+            icon_data: non_empty(&self.icon_data),
+            shortcut: non_empty(&self.shortcut),
+            disposition: (self.disposition != Disposition::Normal).then_some(self.disposition),
+            accessible_desc: non_empty_str(&self.accessible_desc),
+            ..Default::default()
+        }
+    }
 }
 
+pub type ButtonOptions = ItemOptions;
+
+pub type SubMenuOptions = ItemOptions;
+
 #[derive(Debug, Clone, Default)]
 pub struct RadioOptions {
-    pub label: String,
-    pub enabled: bool,
-    pub icon_name: String,
+    pub item: ItemOptions,
     pub toggle_type: ToggleType,
     pub toggle_state: ToggleState,
 }
 
+impl RadioOptions {
+    fn property(&self) -> MenuProperty {
+        MenuProperty {
+            toggle_type: Some(self.toggle_type),
+            toggle_state: Some(self.toggle_state),
+            ..self.item.property()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckboxOptions {
+    pub item: ItemOptions,
+    pub toggle_state: ToggleState,
+}
+
+impl CheckboxOptions {
+    fn property(&self) -> MenuProperty {
+        MenuProperty {
+            toggle_type: Some(ToggleType::Checkmark),
+            toggle_state: Some(self.toggle_state),
+            ..self.item.property()
+        }
+    }
+}
+
+fn non_empty<T: Clone>(value: &[T]) -> Option<Vec<T>> {
+    (!value.is_empty()).then(|| value.to_vec())
+}
+
+fn non_empty_str(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
 impl<Message: Clone> From<&MenuUnit<Message>> for MenuItem {
     fn from(value: &MenuUnit<Message>) -> Self {
         let IdOrGroup::Id(id) = value.id_or_ids() else {
@@ -264,12 +772,27 @@ impl<Message: Clone> MenuUnit<Message> {
     pub fn try_change_label(&mut self, new_label: String) {
         match self {
             Self::RadioButton {
-                options: RadioOptions { label, .. },
+                options:
+                    RadioOptions {
+                        item: ItemOptions { label, .. },
+                        ..
+                    },
+                ..
+            }
+            | Self::SubMenu {
+                options: ItemOptions { label, .. },
                 ..
             }
-            | Self::SubMenu { label, .. }
             | Self::StandardButton {
-                options: ButtonOptions { label, .. },
+                options: ItemOptions { label, .. },
+                ..
+            }
+            | Self::Checkbox {
+                options:
+                    CheckboxOptions {
+                        item: ItemOptions { label, .. },
+                        ..
+                    },
                 ..
             } => {
                 *label = new_label;
@@ -290,10 +813,12 @@ impl<Message: Clone> MenuUnit<Message> {
                     }
                 }
             }
-            Self::RadioButton { id, message, .. } | Self::StandardButton { id, message, .. } => {
-                if fid == *id {
-                    return Some(message.clone());
-                }
+            Self::RadioButton { id, message, .. }
+            | Self::StandardButton { id, message, .. }
+            | Self::Checkbox { id, message, .. }
+                if fid == *id =>
+            {
+                return Some(message.clone());
             }
             _ => {}
         }
@@ -302,37 +827,15 @@ impl<Message: Clone> MenuUnit<Message> {
     fn get_property(&self) -> Option<MenuProperty> {
         match self {
             Self::Root { .. } => Some(MenuProperty::root()),
-            Self::SubMenu { label, .. } => Some(MenuProperty::submenu(label.clone())),
-            Self::StandardButton {
-                options:
-                    ButtonOptions {
-                        label,
-                        enabled,
-                        icon_name,
-                    },
-                ..
-            } => Some(MenuProperty {
-                label: Some(label.clone()),
-                icon_name: Some(icon_name.clone()),
-                enabled: Some(*enabled),
-                ..Default::default()
+            Self::SubMenu { options, .. } => Some(MenuProperty {
+                children_display: Some("submenu".to_owned()),
+                ..options.property()
             }),
-            Self::RadioButton {
-                options:
-                    RadioOptions {
-                        label,
-                        enabled,
-                        icon_name,
-                        toggle_state,
-                        toggle_type,
-                    },
-                ..
-            } => Some(MenuProperty {
-                label: Some(label.clone()),
-                icon_name: Some(icon_name.clone()),
-                enabled: Some(*enabled),
-                toggle_type: Some(*toggle_type),
-                toggle_state: Some(*toggle_state),
+            Self::StandardButton { options, .. } => Some(options.property()),
+            Self::RadioButton { options, .. } => Some(options.property()),
+            Self::Checkbox { options, .. } => Some(options.property()),
+            Self::Separator { .. } => Some(MenuProperty {
+                item_type: Some(MenuItemType::Separator),
                 ..Default::default()
             }),
             Self::RadioGroup { .. } => None,
@@ -355,7 +858,9 @@ impl<Message: Clone> MenuUnit<Message> {
             Self::Root { .. } => IdOrGroup::Id(Id::MAIN),
             Self::SubMenu { id, .. }
             | Self::StandardButton { id, .. }
-            | Self::RadioButton { id, .. } => IdOrGroup::Id(*id),
+            | Self::RadioButton { id, .. }
+            | Self::Checkbox { id, .. }
+            | Self::Separator { id, .. } => IdOrGroup::Id(*id),
             Self::RadioGroup { selections, .. } => {
                 let mut groups = vec![];
                 for selection in selections {
@@ -373,6 +878,7 @@ impl<Message: Clone> MenuUnit<Message> {
     pub fn button(options: ButtonOptions, message: Message) -> Self {
         Self::StandardButton {
             id: Id::unique(),
+            key: None,
             options,
             message,
         }
@@ -381,10 +887,144 @@ impl<Message: Clone> MenuUnit<Message> {
         Self::Root { sub_menus: vec![] }
     }
     pub fn sub_menu(label: String) -> Self {
+        Self::sub_menu_with_options(SubMenuOptions {
+            label,
+            enabled: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn sub_menu_with_options(options: SubMenuOptions) -> Self {
         Self::SubMenu {
             id: Id::unique(),
-            label,
+            key: None,
+            options,
             sub_menus: vec![],
+            dynamic: None,
+        }
+    }
+
+    /// Make a submenu dynamic: its children are built by the
+    /// [`crate::Tray::with_populate_sub_menu`] callback with `message` each
+    /// time it is about to be shown.
+    pub fn dynamic(mut self, message: Message) -> Self {
+        if let Self::SubMenu { dynamic, .. } = &mut self {
+            *dynamic = Some(message);
+        }
+        self
+    }
+
+    /// Attach a stable key, so the unit keeps its id when the menu is rebuilt.
+    ///
+    /// Has no effect on the root and on radio groups, whose buttons are keyed
+    /// with [`RadioGroupBuilder::append_with_key`].
+    pub fn with_key(mut self, new_key: impl Into<MenuKey>) -> Self {
+        if let Self::SubMenu { key, .. }
+        | Self::StandardButton { key, .. }
+        | Self::RadioButton { key, .. }
+        | Self::Checkbox { key, .. }
+        | Self::Separator { key, .. } = &mut self
+        {
+            *key = Some(new_key.into());
+        }
+        self
+    }
+
+    fn id_mut(&mut self) -> Option<&mut Id> {
+        match self {
+            Self::SubMenu { id, .. }
+            | Self::StandardButton { id, .. }
+            | Self::RadioButton { id, .. }
+            | Self::Checkbox { id, .. }
+            | Self::Separator { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self) -> Option<&MenuKey> {
+        match self {
+            Self::SubMenu { key, .. }
+            | Self::StandardButton { key, .. }
+            | Self::RadioButton { key, .. }
+            | Self::Checkbox { key, .. }
+            | Self::Separator { key, .. } => key.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The state of a checkbox or radio button.
+    pub fn toggle_state(&self) -> Option<ToggleState> {
+        match self {
+            Self::RadioButton {
+                options: RadioOptions { toggle_state, .. },
+                ..
+            }
+            | Self::Checkbox {
+                options: CheckboxOptions { toggle_state, .. },
+                ..
+            } => Some(*toggle_state),
+            _ => None,
+        }
+    }
+
+    /// The selected button of a radio group.
+    pub fn selected(&self) -> Option<&Self> {
+        let Self::RadioGroup { selections } = self else {
+            return None;
+        };
+        selections
+            .iter()
+            .find(|selection| selection.toggle_state() == Some(ToggleState::Selected))
+    }
+
+    /// Apply a click on `id`: flip a checkbox, or select the button `id` of a
+    /// radio group and unselect the others. A no-op for other units.
+    fn toggle(&mut self, id: i32) {
+        match self {
+            Self::Checkbox {
+                options: CheckboxOptions { toggle_state, .. },
+                ..
+            } => {
+                *toggle_state = match toggle_state {
+                    ToggleState::Selected => ToggleState::UnSelected,
+                    ToggleState::UnSelected | ToggleState::TriState => ToggleState::Selected,
+                };
+            }
+            Self::RadioGroup { selections } => {
+                for selection in selections {
+                    if let Self::RadioButton {
+                        id: oid,
+                        options: RadioOptions { toggle_state, .. },
+                        ..
+                    } = selection
+                    {
+                        *toggle_state = if **oid == id {
+                            ToggleState::Selected
+                        } else {
+                            ToggleState::UnSelected
+                        };
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// An item with a checkmark, flipped by the crate each time it is clicked.
+    pub fn checkbox(options: CheckboxOptions, message: Message) -> Self {
+        Self::Checkbox {
+            id: Id::unique(),
+            key: None,
+            options,
+            message,
+        }
+    }
+
+    /// A divider between two sections of a menu.
+    pub fn separator() -> Self {
+        Self::Separator {
+            id: Id::unique(),
+            key: None,
         }
     }
 
@@ -406,6 +1046,8 @@ impl<Message: Clone> MenuUnit<Message> {
             MenuUnit::SubMenu { .. } => MenuType::SubMenu,
             MenuUnit::RadioGroup { .. } => MenuType::RadioGroup,
             MenuUnit::StandardButton { .. } | MenuUnit::RadioButton { .. } => MenuType::Button,
+            MenuUnit::Checkbox { .. } => MenuType::Checkbox,
+            MenuUnit::Separator { .. } => MenuType::Separator,
         }
     }
 
@@ -458,6 +1100,7 @@ pub struct MenuItem {
 
 #[derive(Clone, PartialEq, Type, Serialize, Deserialize, OwnedValue, Value, Debug, Default)]
 #[zvariant(signature = "s", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum MenuStatus {
     #[default]
     Normal,
@@ -484,50 +1127,26 @@ impl MenuItem {
         }
     }
 
-    #[allow(unused)]
-    #[allow(clippy::only_used_in_recursion)]
+    /// Find the node `parent_id` and return it down to `recursion_depth`
+    /// levels, `-1` meaning the whole subtree.
     pub fn get_filiter(
         &self,
         parent_id: i32,
         recursion_depth: i32,
         property_names: &[&str],
     ) -> Option<MenuItem> {
-        if *self.id == parent_id {
-            let mut new_menu = MenuItem {
-                id: self.id,
-                property: self.property.clone(),
-                sub_menus: vec![],
-            };
-
-            let next_reversion_depth = recursion_depth - 1;
-            if next_reversion_depth != 0 {
-                for menu in self.sub_menus.as_slice() {
-                    let menu: MenuItem = menu.clone().try_into().unwrap();
-                    let next_menu = menu.filiter(next_reversion_depth, property_names);
-                    new_menu = new_menu.push_sub_menu(next_menu);
-                }
-            }
-            return Some(new_menu);
-        }
-        None
+        Some(filiter(
+            self.layout().find(parent_id)?,
+            recursion_depth,
+            property_names,
+        ))
     }
 
-    #[allow(clippy::only_used_in_recursion)]
-    fn filiter(&self, recursion_depth: i32, property_names: &[&str]) -> MenuItem {
-        let mut new_menu = MenuItem {
-            id: self.id,
-            property: self.property.clone(),
-            sub_menus: vec![],
-        };
-
-        let next_reversion_depth = recursion_depth - 1;
-        for menu in self.sub_menus.as_slice() {
-            let menu: MenuItem = menu.clone().try_into().unwrap();
-            let next_menu = menu.filiter(next_reversion_depth, property_names);
-            new_menu = new_menu.push_sub_menu(next_menu);
-        }
-
-        new_menu
+    /// Decode the whole tree at once, instead of each level of it on every lookup.
+    fn layout(&self) -> MenuLayout {
+        self.clone()
+            .try_into()
+            .expect("sub menus are encoded menu items")
     }
 
     pub fn push_sub_menu(mut self, menu: MenuItem) -> Self {
@@ -535,43 +1154,79 @@ impl MenuItem {
         self
     }
 
-    pub fn get_property(&self, id: i32, name: String) -> Option<PropertyItem> {
-        if *self.id == id {
-            return Some(PropertyItem {
-                id,
-                item: self.property.clone(),
-            });
-        }
-        let sub_menus: Vec<MenuItem> = self
-            .sub_menus
-            .iter()
-            .map(|submenu| submenu.clone().try_into().unwrap())
-            .collect();
+    pub fn find(&self, id: i32) -> Option<MenuItem> {
+        Some(filiter(self.layout().find(id)?, -1, &[]))
+    }
 
-        for sub_menu in sub_menus {
-            let property = sub_menu.get_property(id, name.clone());
-            if property.is_some() {
-                return property;
+    pub fn get_property(&self, id: i32, property_names: &[&str]) -> Option<PropertyItem> {
+        property_item(&self.layout(), id, property_names)
+    }
+
+    /// The ids of this item and of all its descendants.
+    pub fn ids(&self) -> Vec<i32> {
+        fn collect(layout: &MenuLayout, ids: &mut Vec<i32>) {
+            ids.push(layout.id);
+            for child in &layout.children {
+                collect(child, ids);
             }
         }
-
-        None
+        let mut ids = vec![];
+        collect(&self.layout(), &mut ids);
+        ids
+    }
+
+    /// The properties of the items `ids`, or of every item when empty.
+    /// Unknown ids are skipped.
+    pub fn get_property_groups(&self, ids: &[i32], property_names: &[&str]) -> Vec<PropertyItem> {
+        let all_ids;
+        let ids = if ids.is_empty() {
+            all_ids = self.ids();
+            &all_ids
+        } else {
+            ids
+        };
+        let layout = self.layout();
+        ids.iter()
+            .filter_map(|id| property_item(&layout, *id, property_names))
+            .collect()
     }
+}
 
-    pub fn get_property_groups(
-        &self,
-        ids: Vec<i32>,
-        _property_names: Vec<String>,
-    ) -> Vec<PropertyItem> {
-        let mut output = vec![];
-        for id in ids {
-            if let Some(property) = self.get_property(id, "".to_string()) {
-                output.push(property);
-            }
-        }
+fn filiter(layout: &MenuLayout, recursion_depth: i32, property_names: &[&str]) -> MenuItem {
+    let mut new_menu = MenuItem {
+        id: Id(layout.id),
+        property: layout.property.filter(property_names),
+        sub_menus: vec![],
+    };
 
-        output
+    if recursion_depth == 0 {
+        if !layout.children.is_empty() {
+            new_menu.property.children_display = Some("submenu".to_owned());
+        }
+        return new_menu;
+    }
+    let next_reversion_depth = if recursion_depth < 0 {
+        -1
+    } else {
+        recursion_depth - 1
+    };
+    for child in &layout.children {
+        let next_menu = filiter(child, next_reversion_depth, property_names);
+        new_menu = new_menu.push_sub_menu(next_menu);
     }
+
+    new_menu
+}
+
+fn property_item(layout: &MenuLayout, id: i32, property_names: &[&str]) -> Option<PropertyItem> {
+    Some(PropertyItem {
+        id,
+        item: layout.find(id)?.property.filter(property_names),
+    })
+}
+
+fn unknown_id(id: i32) -> zbus::fdo::Error {
+    zbus::fdo::Error::InvalidArgs(format!("Menu item {id} does not exist"))
 }
 
 #[derive(Type, Debug, Default, Serialize, Deserialize)]
@@ -590,9 +1245,11 @@ pub trait DBusMenuItem {
 
     fn revision(&self, state: &Self::State) -> u32;
 
+    /// Called with an existing `id`, returning whether the host should
+    /// refetch it. Dynamic submenus are handled by [`Self::populate_sub_menu`].
     #[allow(unused)]
     fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
-        Err(zbus::fdo::Error::Failed("Unimplemented".to_string()))
+        Ok(false)
     }
 
     /// AboutToShowGroup method
@@ -602,13 +1259,33 @@ pub trait DBusMenuItem {
         state: &mut Self::State,
         ids: Vec<i32>,
     ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
-        Err(zbus::fdo::Error::Failed("Unimplemented".to_string()))
+        let mut updates_needed = vec![];
+        for id in ids {
+            if self.about_to_show(state, id)? {
+                updates_needed.push(id);
+            }
+        }
+        Ok((updates_needed, vec![]))
     }
 
     fn status(&self, _state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
         Ok(MenuStatus::Normal)
     }
 
+    /// Build the children of a submenu made [`MenuUnit::dynamic`] with
+    /// `message`, called when it is about to be shown.
+    #[allow(unused)]
+    fn populate_sub_menu(
+        &self,
+        state: &mut Self::State,
+        message: Self::Message,
+    ) -> Vec<MenuUnit<Self::Message>> {
+        vec![]
+    }
+
+    /// Checkboxes are flipped before this is called, `button` already holds
+    /// the new [`MenuUnit::toggle_state`]. For a radio button, `button` is
+    /// its whole group, with the clicked one already [`MenuUnit::selected`].
     #[allow(unused)]
     fn on_clicked(
         &self,
@@ -620,6 +1297,42 @@ pub trait DBusMenuItem {
         EventUpdate::None
     }
 
+    /// The submenu `id` was opened, `0` being the menu itself.
+    #[allow(unused)]
+    fn on_opened(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        EventUpdate::None
+    }
+
+    /// The submenu `id` was closed, `0` being the menu itself.
+    #[allow(unused)]
+    fn on_closed(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        EventUpdate::None
+    }
+
+    /// The pointer moved over the item `id`.
+    #[allow(unused)]
+    fn on_hovered(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        EventUpdate::None
+    }
+
     #[allow(unused)]
     fn text_direction(&self, state: &Self::State) -> TextDirection {
         TextDirection::Inherit
@@ -631,13 +1344,228 @@ pub trait DBusMenuItem {
     }
 }
 
+/// Shared programs, kept around to run a tray again after its shutdown.
+impl<T: DBusMenuItem + ?Sized> DBusMenuItem for Arc<T> {
+    type State = T::State;
+    type Message = T::Message;
+
+    fn boot(&self) -> Self::State {
+        (**self).boot()
+    }
+    fn menu(&self) -> MenuTree<Self::Message> {
+        (**self).menu()
+    }
+    fn revision(&self, state: &Self::State) -> u32 {
+        (**self).revision(state)
+    }
+    fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
+        (**self).about_to_show(state, id)
+    }
+    fn about_to_show_group(
+        &self,
+        state: &mut Self::State,
+        ids: Vec<i32>,
+    ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+        (**self).about_to_show_group(state, ids)
+    }
+    fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
+        (**self).status(state)
+    }
+    fn populate_sub_menu(
+        &self,
+        state: &mut Self::State,
+        message: Self::Message,
+    ) -> Vec<MenuUnit<Self::Message>> {
+        (**self).populate_sub_menu(state, message)
+    }
+    fn on_clicked(
+        &self,
+        state: &mut Self::State,
+        button: &mut MenuUnit<Self::Message>,
+        message: Self::Message,
+        timestamp: u32,
+    ) -> EventUpdate {
+        (**self).on_clicked(state, button, message, timestamp)
+    }
+    fn on_opened(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        (**self).on_opened(state, id, data, timestamp)
+    }
+    fn on_closed(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        (**self).on_closed(state, id, data, timestamp)
+    }
+    fn on_hovered(
+        &self,
+        state: &mut Self::State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        (**self).on_hovered(state, id, data, timestamp)
+    }
+    fn text_direction(&self, state: &Self::State) -> TextDirection {
+        (**self).text_direction(state)
+    }
+    fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
+        (**self).icon_theme_path(state)
+    }
+}
+
 pub struct DBusMenuInstance<State, Message>
 where
     Message: Clone,
 {
-    pub(crate) program: Box<dyn DBusMenuItem<State = State, Message = Message> + Send + Sync>,
+    pub(crate) program: Arc<dyn DBusMenuItem<State = State, Message = Message> + Send + Sync>,
     pub(crate) state: State,
     pub(crate) menu_tree: MenuTree<Message>,
+    pub(crate) ids: IdAllocator,
+    /// Bumped on every layout change the crate finds by itself.
+    pub(crate) revision: u32,
+}
+
+impl<State, Message: Clone> DBusMenuInstance<State, Message> {
+    pub(crate) fn new(
+        program: Arc<dyn DBusMenuItem<State = State, Message = Message> + Send + Sync>,
+        state: State,
+        menu_tree: MenuTree<Message>,
+    ) -> Self {
+        let mut instance = DBusMenuInstance {
+            program,
+            state,
+            menu_tree: MenuTree::new(),
+            ids: IdAllocator::default(),
+            revision: 0,
+        };
+        instance.set_menu_tree(menu_tree);
+        instance
+    }
+
+    /// Replace the menu, keeping the ids of the keyed units and of the
+    /// unkeyed ones left in place.
+    pub(crate) fn set_menu_tree(&mut self, mut menu_tree: MenuTree<Message>) {
+        self.ids.assign(&self.menu_tree, &mut menu_tree);
+        if let (Some(previous), Some(sub_menus)) = (
+            self.menu_tree.get_unit().sub_menus(),
+            menu_tree.get_unit_mut().sub_menus_mut(),
+        ) {
+            reuse_ids(previous, sub_menus);
+        }
+        self.menu_tree = menu_tree;
+    }
+
+    /// Replace the menu and return the signals describing the change.
+    pub(crate) fn update_menu_tree(&mut self, menu_tree: MenuTree<Message>) -> MenuChanges {
+        let old = MenuSnapshot::new(&self.menu_tree);
+        self.set_menu_tree(menu_tree);
+        self.changes_since(&old)
+    }
+
+    fn contains(&self, id: i32) -> bool {
+        self.menu_tree.get_unit().find_menu_by_id(id).is_some()
+    }
+
+    /// Rebuild the children of the dynamic submenu `id`, returning whether
+    /// they changed, or `None` when `id` is not a dynamic submenu.
+    fn populate_sub_menu(&mut self, id: i32) -> Option<bool> {
+        let MenuUnit::SubMenu {
+            dynamic: Some(message),
+            ..
+        } = self.menu_tree.get_unit().find_menu_by_id(id)?
+        else {
+            return None;
+        };
+        let message = message.clone();
+        let old = MenuSnapshot::new(&self.menu_tree);
+        let mut children = self.program.populate_sub_menu(&mut self.state, message);
+        // Take the previous children out first, so their keys can be reused
+        let previous = std::mem::take(
+            self.menu_tree
+                .get_unit_mut()
+                .find_menu_by_id_mut(id)?
+                .sub_menus_mut()?,
+        );
+        self.ids
+            .assign_new(&self.menu_tree, &previous, &mut children);
+        reuse_ids(&previous, &mut children);
+        *self
+            .menu_tree
+            .get_unit_mut()
+            .find_menu_by_id_mut(id)?
+            .sub_menus_mut()? = children;
+        let changes = self.changes_since(&old);
+        Some(
+            changes.is_layout_changed()
+                || !changes.updated.is_empty()
+                || !changes.removed.is_empty(),
+        )
+    }
+
+    /// Run the callback of the event `event_id` on `id`, `None` when the item
+    /// or the event is unknown.
+    fn dispatch_event(
+        &mut self,
+        id: i32,
+        event_id: &str,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> Option<EventUpdate> {
+        match event_id {
+            "clicked" => {
+                let menu = self.menu_tree.get_unit_mut();
+                let (button, message) = menu.find_menu_and_message_by_id_mut(id)?;
+                if !matches!(
+                    button.unit_type(),
+                    MenuType::Button | MenuType::RadioGroup | MenuType::Checkbox
+                ) {
+                    return None;
+                }
+                button.toggle(id);
+                Some(
+                    self.program
+                        .on_clicked(&mut self.state, button, message, timestamp),
+                )
+            }
+            "opened" | "closed" | "hovered" => {
+                self.menu_tree.get_unit().find_menu_by_id(id)?;
+                let update = match event_id {
+                    "opened" => self.program.on_opened(&mut self.state, id, data, timestamp),
+                    "closed" => self.program.on_closed(&mut self.state, id, data, timestamp),
+                    _ => self
+                        .program
+                        .on_hovered(&mut self.state, id, data, timestamp),
+                };
+                Some(update)
+            }
+            _ => None,
+        }
+    }
+
+    /// What changed in the menu since `old` was taken.
+    fn changes_since(&mut self, old: &MenuSnapshot) -> MenuChanges {
+        let changes = old.diff(&MenuSnapshot::new(&self.menu_tree));
+        if changes.is_layout_changed() {
+            self.revision = self.revision.wrapping_add(1);
+        }
+        changes
+    }
+
+    /// The revision of the layout, as announced to the hosts.
+    pub(crate) fn revision(&self) -> u32 {
+        self.program
+            .revision(&self.state)
+            .wrapping_add(self.revision)
+    }
 }
 
 pub trait DBusMenuBootFn<State> {
@@ -753,6 +1681,95 @@ where
     }
 }
 
+pub trait OnOpenedFn<State> {
+    fn on_opened(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate;
+}
+
+impl<T, State> OnOpenedFn<State> for T
+where
+    T: Fn(&mut State, i32, &OwnedValue, u32) -> EventUpdate,
+{
+    fn on_opened(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        self(state, id, data, timestamp)
+    }
+}
+
+pub trait OnClosedFn<State> {
+    fn on_closed(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate;
+}
+
+impl<T, State> OnClosedFn<State> for T
+where
+    T: Fn(&mut State, i32, &OwnedValue, u32) -> EventUpdate,
+{
+    fn on_closed(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        self(state, id, data, timestamp)
+    }
+}
+
+pub trait OnHoveredFn<State> {
+    fn on_hovered(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate;
+}
+
+impl<T, State> OnHoveredFn<State> for T
+where
+    T: Fn(&mut State, i32, &OwnedValue, u32) -> EventUpdate,
+{
+    fn on_hovered(
+        &self,
+        state: &mut State,
+        id: i32,
+        data: &OwnedValue,
+        timestamp: u32,
+    ) -> EventUpdate {
+        self(state, id, data, timestamp)
+    }
+}
+
+pub trait PopulateSubMenuFn<State, Message: Clone> {
+    fn populate_sub_menu(&self, state: &mut State, message: Message) -> Vec<MenuUnit<Message>>;
+}
+
+impl<T, State, Message> PopulateSubMenuFn<State, Message> for T
+where
+    T: Fn(&mut State, Message) -> Vec<MenuUnit<Message>>,
+    Message: Clone,
+{
+    fn populate_sub_menu(&self, state: &mut State, message: Message) -> Vec<MenuUnit<Message>> {
+        self(state, message)
+    }
+}
+
 pub trait TextDirectionFn<State> {
     fn text_direction(&self, state: &State) -> TextDirection;
 }
@@ -819,12 +1836,43 @@ where
     Message: 'static + Send + Sync + Clone,
 {
     fn about_to_show(&mut self, id: i32) -> zbus::fdo::Result<bool> {
+        if !self.contains(id) {
+            return Err(unknown_id(id));
+        }
+        if let Some(need_update) = self.populate_sub_menu(id) {
+            return Ok(need_update);
+        }
         self.program.about_to_show(&mut self.state, id)
     }
 
     /// AboutToShowGroup method
     fn about_to_show_group(&mut self, ids: Vec<i32>) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
-        self.program.about_to_show_group(&mut self.state, ids)
+        let mut updates_needed = vec![];
+        let mut others = vec![];
+        let mut id_errors = vec![];
+        for id in ids.iter().copied() {
+            if !self.contains(id) {
+                id_errors.push(id);
+                continue;
+            }
+            match self.populate_sub_menu(id) {
+                Some(true) => updates_needed.push(id),
+                Some(false) => {}
+                None => others.push(id),
+            }
+        }
+        if !ids.is_empty() && id_errors.len() == ids.len() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "None of the menu item ids {ids:?} exist"
+            )));
+        }
+        if !others.is_empty() {
+            let (mut more, mut more_errors) =
+                self.program.about_to_show_group(&mut self.state, others)?;
+            updates_needed.append(&mut more);
+            id_errors.append(&mut more_errors);
+        }
+        Ok((updates_needed, id_errors))
     }
 
     // NOTE: this should not implemented by user
@@ -838,10 +1886,10 @@ where
         let property_names: Vec<&str> = property_names.iter().map(|name| name.as_str()).collect();
         let menuitem: MenuItem = (&self.menu_tree).into();
         Ok((
-            self.program.revision(&self.state),
+            self.revision(),
             menuitem
                 .get_filiter(parent_id, recursion_depth, &property_names)
-                .ok_or(zbus::fdo::Error::Failed("UnFounded".to_string()))?,
+                .ok_or_else(|| unknown_id(parent_id))?,
         ))
     }
 
@@ -850,15 +1898,23 @@ where
         ids: Vec<i32>,
         property_names: Vec<String>,
     ) -> zbus::fdo::Result<Vec<PropertyItem>> {
+        let property_names: Vec<&str> = property_names.iter().map(|name| name.as_str()).collect();
         let menuitem: MenuItem = (&self.menu_tree).into();
-        Ok(menuitem.get_property_groups(ids, property_names))
+        Ok(menuitem.get_property_groups(&ids, &property_names))
     }
 
-    fn get_property(&mut self, id: i32, name: String) -> zbus::fdo::Result<PropertyItem> {
+    fn get_property(&mut self, id: i32, name: String) -> zbus::fdo::Result<OwnedValue> {
         let menuitem: MenuItem = (&self.menu_tree).into();
         menuitem
-            .get_property(id, name)
-            .ok_or(zbus::fdo::Error::Failed("Unfounded".to_string()))
+            .find(id)
+            .ok_or_else(|| unknown_id(id))?
+            .property
+            .get(&name)
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!(
+                    "Menu item {id} has no property called {name}"
+                ))
+            })
     }
 
     /// Version property
@@ -878,27 +1934,21 @@ where
         &mut self,
         id: i32,
         event_id: String,
-        _data: zbus::zvariant::OwnedValue,
+        data: zbus::zvariant::OwnedValue,
         timestamp: u32,
         #[zbus(signal_emitter)] cxts: SignalEmitter<'_>,
     ) -> zbus::fdo::Result<()> {
-        let menu = self.menu_tree.get_unit_mut();
-
-        let Some((button, message)) = menu.find_menu_and_message_by_id_mut(id) else {
+        if !self.contains(id) {
+            return Err(unknown_id(id));
+        }
+        let old = MenuSnapshot::new(&self.menu_tree);
+        let Some(need_update) = self.dispatch_event(id, &event_id, &data, timestamp) else {
             return Ok(());
         };
-        let need_update = match event_id.as_str() {
-            "clicked" => {
-                if !matches!(button.unit_type(), MenuType::Button | MenuType::RadioGroup) {
-                    return Ok(());
-                }
-                self.program
-                    .on_clicked(&mut self.state, button, message, timestamp)
-            }
-            _ => EventUpdate::None,
-        };
 
-        let revision = self.program.revision(&self.state);
+        let changes = self.changes_since(&old);
+        let revision = self.revision();
+        let _ = changes.emit::<State, Message>(&cxts, revision).await;
         match need_update {
             EventUpdate::UpdateCurrent => {
                 let _ =
@@ -921,26 +1971,18 @@ where
         events: Vec<(i32, String, zbus::zvariant::OwnedValue, u32)>,
         #[zbus(signal_emitter)] cxts: SignalEmitter<'_>,
     ) -> zbus::fdo::Result<Vec<i32>> {
-        let mut output = vec![];
+        let mut id_errors = vec![];
         let mut update_all = false;
         let mut update_parents: Vec<i32> = vec![];
-        for (id, event_id, _data, timestamp) in events {
-            let menu = self.menu_tree.get_unit_mut();
-            let Some((button, message)) = menu.find_menu_and_message_by_id_mut(id) else {
+        let old = MenuSnapshot::new(&self.menu_tree);
+        let count = events.len();
+        for (id, event_id, data, timestamp) in events {
+            if !self.contains(id) {
+                id_errors.push(id);
+                continue;
+            }
+            let Some(need_update) = self.dispatch_event(id, &event_id, &data, timestamp) else {
                 continue;
-            };
-
-            let need_update = match event_id.as_str() {
-                "clicked" => {
-                    if !matches!(button.unit_type(), MenuType::Button | MenuType::RadioGroup) {
-                        continue;
-                    }
-                    self.program
-                        .on_clicked(&mut self.state, button, message, timestamp)
-                }
-                _ => {
-                    continue;
-                }
             };
             match need_update {
                 EventUpdate::None => {
@@ -950,14 +1992,18 @@ where
                     update_all = true;
                 }
                 EventUpdate::UpdateCurrent => {
-                    if let IdOrGroup::Id(id) = menu.id_or_ids() {
-                        update_parents.push(*id);
-                    }
+                    update_parents.push(id);
                 }
             };
-            output.push(id);
         }
-        let revision = self.program.revision(&self.state);
+        if count != 0 && id_errors.len() == count {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "None of the menu item ids {id_errors:?} exist"
+            )));
+        }
+        let changes = self.changes_since(&old);
+        let revision = self.revision();
+        let _ = changes.emit::<State, Message>(&cxts, revision).await;
         if update_all {
             let _ = DBusMenuInstance::<State, Message>::layout_updated(&cxts, revision, *Id::MAIN)
                 .await;
@@ -967,7 +2013,7 @@ where
                     DBusMenuInstance::<State, Message>::layout_updated(&cxts, revision, id).await;
             }
         }
-        Ok(output)
+        Ok(id_errors)
     }
 
     /// TextDirection property
@@ -1005,3 +2051,308 @@ where
         parent: i32,
     ) -> zbus::Result<()>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(label: &str) -> MenuUnit<()> {
+        MenuUnit::button(
+            ButtonOptions {
+                label: label.to_owned(),
+                ..Default::default()
+            },
+            (),
+        )
+    }
+
+    fn assigned_ids(ids: &mut IdAllocator, mut tree: MenuTree<()>) -> Vec<i32> {
+        ids.assign(&MenuTree::new(), &mut tree);
+        MenuItem::from(&tree).ids()
+    }
+
+    /// What `value` looks like on the bus, read back as `T`.
+    fn on_the_wire<T>(value: &(impl Serialize + Type)) -> T
+    where
+        T: for<'de> Deserialize<'de> + Type,
+    {
+        let ctxt = zvariant::serialized::Context::new_dbus(zvariant::LE, 0);
+        let data = zvariant::to_bytes(ctxt, value).unwrap();
+        data.deserialize().unwrap().0
+    }
+
+    fn wire_str<'a>(properties: &'a HashMap<String, OwnedValue>, name: &str) -> &'a str {
+        <&str>::try_from(&properties[name]).unwrap()
+    }
+
+    #[test]
+    fn enums_use_the_spec_names_on_the_wire() {
+        for (toggle_type, name) in [
+            (ToggleType::Checkmark, "checkmark"),
+            (ToggleType::Radio, "radio"),
+            (ToggleType::None, ""),
+        ] {
+            let property = MenuProperty {
+                toggle_type: Some(toggle_type),
+                ..Default::default()
+            };
+            let properties: HashMap<String, OwnedValue> = on_the_wire(&property);
+            assert_eq!(wire_str(&properties, "toggle-type"), name);
+            let value = property.get(MenuProperty::TOGGLE_TYPE).unwrap();
+            assert_eq!(<&str>::try_from(&value).unwrap(), name);
+        }
+        for (direction, name) in [
+            (TextDirection::Inherit, "inherit"),
+            (TextDirection::Ltr, "ltr"),
+            (TextDirection::Rtl, "rtl"),
+        ] {
+            assert_eq!(on_the_wire::<String>(&direction), name);
+        }
+    }
+
+    /// Serves a menu that never changes.
+    struct Fixed;
+
+    impl DBusMenuItem for Fixed {
+        type State = ();
+        type Message = ();
+
+        fn boot(&self) {}
+        fn menu(&self) -> MenuTree<()> {
+            MenuTree::new()
+        }
+        fn revision(&self, _state: &()) -> u32 {
+            0
+        }
+    }
+
+    type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+    /// The properties of each child of the root, as sent by GetLayout.
+    fn layout_properties(tree: MenuTree<()>) -> Vec<HashMap<String, OwnedValue>> {
+        let mut instance = DBusMenuInstance::new(Arc::new(Fixed), (), tree);
+        let (_, layout) = instance.get_layout(0, -1, vec![]).unwrap();
+        let (_, _, children): Layout = on_the_wire(&layout);
+        children
+            .iter()
+            .map(|child| {
+                let (_, properties, _): Layout = child.try_clone().unwrap().try_into().unwrap();
+                properties
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checkboxes_are_sent_as_checkmarks() {
+        let checkbox = |toggle_state| {
+            MenuUnit::checkbox(
+                CheckboxOptions {
+                    item: ItemOptions {
+                        label: "check".to_owned(),
+                        ..Default::default()
+                    },
+                    toggle_state,
+                },
+                (),
+            )
+        };
+        let properties = layout_properties(
+            MenuTree::new()
+                .push(checkbox(ToggleState::Selected))
+                .push(checkbox(ToggleState::UnSelected)),
+        );
+        for (properties, toggle_state) in properties.iter().zip([1, 0]) {
+            assert_eq!(wire_str(properties, "toggle-type"), "checkmark");
+            assert_eq!(
+                i32::try_from(&properties["toggle-state"]).unwrap(),
+                toggle_state
+            );
+        }
+    }
+
+    #[test]
+    fn defaults_of_the_spec_are_not_sent() {
+        let item = |hidden, disposition| {
+            MenuUnit::button(
+                ItemOptions {
+                    label: "item".to_owned(),
+                    hidden,
+                    disposition,
+                    ..Default::default()
+                },
+                (),
+            )
+        };
+        let properties = layout_properties(
+            MenuTree::new()
+                .push(item(false, Disposition::Normal))
+                .push(item(true, Disposition::Alert)),
+        );
+        assert!(!properties[0].contains_key("visible"));
+        assert!(!properties[0].contains_key("disposition"));
+        assert!(!bool::try_from(&properties[1]["visible"]).unwrap());
+        assert_eq!(wire_str(&properties[1], "disposition"), "alert");
+    }
+
+    #[test]
+    fn radio_buttons_are_sent_as_radios() {
+        let radio = |label: &str, toggle_state| RadioOptions {
+            item: ItemOptions {
+                label: label.to_owned(),
+                ..Default::default()
+            },
+            toggle_state,
+            ..Default::default()
+        };
+        let group = RadioGroupBuilder::new()
+            .append(radio("a", ToggleState::UnSelected), ())
+            .append(radio("b", ToggleState::Selected), ());
+        let properties = layout_properties(MenuTree::new().push(MenuUnit::radio_group(group)));
+        assert_eq!(properties.len(), 2);
+        for (properties, toggle_state) in properties.iter().zip([0, 1]) {
+            assert_eq!(wire_str(properties, "toggle-type"), "radio");
+            assert_eq!(
+                i32::try_from(&properties["toggle-state"]).unwrap(),
+                toggle_state
+            );
+        }
+    }
+
+    #[test]
+    fn variant_names_are_still_read() {
+        assert_eq!(
+            on_the_wire::<ToggleType>(&"Checkmark"),
+            ToggleType::Checkmark
+        );
+        assert_eq!(on_the_wire::<TextDirection>(&"Rtl"), TextDirection::Rtl);
+    }
+
+    #[test]
+    fn keyed_units_keep_their_ids() {
+        let mut ids = IdAllocator::default();
+        let tree = || {
+            MenuTree::new()
+                .push(button("a").with_key("a"))
+                .push(button("b"))
+                .push(button("c").with_key("c"))
+        };
+        let first = assigned_ids(&mut ids, tree());
+        let second = assigned_ids(&mut ids, tree());
+        assert_eq!(first[0], 0);
+        assert_eq!(first[1], second[1]);
+        assert_eq!(first[3], second[3]);
+        assert_ne!(first[2], second[2]);
+    }
+
+    #[test]
+    fn duplicated_keys_do_not_share_ids() {
+        let mut ids = IdAllocator::default();
+        let tree = MenuTree::new()
+            .push(button("a").with_key("same"))
+            .push(button("b").with_key("same"))
+            .push(MenuUnit::sub_menu("sub".to_owned()).push(button("c").with_key("same")));
+        let assigned = assigned_ids(&mut ids, tree);
+        let unique: HashSet<_> = assigned.iter().collect();
+        assert_eq!(unique.len(), assigned.len());
+    }
+
+    #[test]
+    fn dropped_keys_are_forgotten() {
+        let mut ids = IdAllocator::default();
+        let first = assigned_ids(&mut ids, MenuTree::new().push(button("a").with_key("a")));
+        assigned_ids(&mut ids, MenuTree::new().push(button("b")));
+        let third = assigned_ids(&mut ids, MenuTree::new().push(button("a").with_key("a")));
+        assert_ne!(first[1], third[1]);
+    }
+
+    #[test]
+    fn new_units_do_not_take_ids_of_the_tree() {
+        let mut ids = IdAllocator::default();
+        let mut tree = MenuTree::new().push(button("a").with_key("a"));
+        ids.assign(&MenuTree::new(), &mut tree);
+        let mut units = vec![button("a").with_key("a"), button("b").with_key("b")];
+        ids.assign_new(&tree, &[], &mut units);
+        let mut assigned = MenuItem::from(&tree).ids();
+        for unit in units {
+            assigned.extend(
+                MenuItem::from(&MenuTree::new().push(unit))
+                    .ids()
+                    .into_iter()
+                    .skip(1),
+            );
+        }
+        let unique: HashSet<_> = assigned.iter().collect();
+        assert_eq!(unique.len(), assigned.len());
+    }
+
+    #[test]
+    fn ids_wrap_around_without_taking_used_ones() {
+        let mut ids = IdAllocator {
+            next: i32::MAX,
+            ..Default::default()
+        };
+        let mut previous = MenuTree::new().push(button("a")).push(button("b"));
+        ids.assign(&MenuTree::new(), &mut previous);
+        assert_eq!(MenuItem::from(&previous).ids(), [0, i32::MAX, 1]);
+        let mut tree = MenuTree::new().push(button("c")).push(button("d"));
+        ids.assign(&previous, &mut tree);
+        assert_eq!(MenuItem::from(&tree).ids(), [0, 2, 3]);
+    }
+
+    #[test]
+    fn rebuilding_unkeyed_units_keeps_their_ids() {
+        let tree = |label: &str| {
+            MenuTree::new()
+                .push(button(label))
+                .push(MenuUnit::sub_menu("sub".to_owned()).push(button("child")))
+        };
+        let mut instance = DBusMenuInstance::new(Arc::new(Fixed), (), tree("0"));
+        let ids = MenuItem::from(&instance.menu_tree).ids();
+        let changes = instance.update_menu_tree(tree("1"));
+        assert!(!changes.is_layout_changed());
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(MenuItem::from(&instance.menu_tree).ids(), ids);
+    }
+
+    /// Fills its dynamic submenu with `state` buttons, the second one keyed.
+    struct Dynamic;
+
+    impl DBusMenuItem for Dynamic {
+        type State = usize;
+        type Message = ();
+
+        fn boot(&self) -> usize {
+            2
+        }
+        fn menu(&self) -> MenuTree<()> {
+            MenuTree::new().push(MenuUnit::sub_menu("dynamic".to_owned()).dynamic(()))
+        }
+        fn revision(&self, _state: &usize) -> u32 {
+            0
+        }
+        fn populate_sub_menu(&self, state: &mut usize, _message: ()) -> Vec<MenuUnit<()>> {
+            (0..*state)
+                .map(|index| match index {
+                    1 => button("keyed").with_key("keyed"),
+                    _ => button(&index.to_string()),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn repopulating_the_same_children_is_not_a_change() {
+        let program = Dynamic;
+        let menu = program.menu();
+        let mut instance = DBusMenuInstance::new(Arc::new(program), 2, menu);
+        let id = MenuItem::from(&instance.menu_tree).ids()[1];
+        assert_eq!(instance.populate_sub_menu(id), Some(true));
+        let revision = instance.revision();
+        assert_eq!(instance.populate_sub_menu(id), Some(false));
+        assert_eq!(instance.revision(), revision);
+
+        instance.state = 3;
+        assert_eq!(instance.populate_sub_menu(id), Some(true));
+        assert_ne!(instance.revision(), revision);
+    }
+}
@@ -0,0 +1,454 @@
+//! # D-Bus interface proxy for: `com.canonical.dbusmenu`
+//!
+//! Client side of the menu protocol, used to render and drive the menus
+//! exported by other applications.
+use futures_lite::{Stream, StreamExt};
+use zbus::{
+    proxy,
+    zvariant::{self, OwnedValue, Value},
+};
+
+use super::{MenuItem, MenuProperty, MenuStatus, PropertyItem, TextDirection};
+
+#[proxy(
+    interface = "com.canonical.dbusmenu",
+    default_path = "/MenuBar",
+    gen_blocking = false
+)]
+pub trait DBusMenu {
+    /// AboutToShow method
+    fn about_to_show(&self, id: i32) -> zbus::Result<bool>;
+
+    /// AboutToShowGroup method
+    fn about_to_show_group(&self, ids: &[i32]) -> zbus::Result<(Vec<i32>, Vec<i32>)>;
+
+    /// Event method
+    fn event(&self, id: i32, event_id: &str, data: &Value<'_>, timestamp: u32) -> zbus::Result<()>;
+
+    /// EventGroup method
+    fn event_group(&self, events: &[(i32, &str, Value<'_>, u32)]) -> zbus::Result<Vec<i32>>;
+
+    /// GetGroupProperties method
+    fn get_group_properties(
+        &self,
+        ids: &[i32],
+        property_names: &[&str],
+    ) -> zbus::Result<Vec<PropertyItem>>;
+
+    /// GetLayout method
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: i32,
+        property_names: &[&str],
+    ) -> zbus::Result<(u32, MenuItem)>;
+
+    /// GetProperty method
+    fn get_property(&self, id: i32, name: &str) -> zbus::Result<OwnedValue>;
+
+    /// ItemActivationRequested signal
+    #[zbus(signal)]
+    fn item_activation_requested(&self, id: i32, timestamp: u32) -> zbus::Result<()>;
+
+    /// ItemsPropertiesUpdated signal
+    #[zbus(signal)]
+    fn items_properties_updated(
+        &self,
+        updated_props: Vec<(i32, MenuProperty)>,
+        removed_props: Vec<(i32, Vec<String>)>,
+    ) -> zbus::Result<()>;
+
+    /// LayoutUpdated signal
+    #[zbus(signal)]
+    fn layout_updated(&self, revision: u32, parent: i32) -> zbus::Result<()>;
+
+    /// IconThemePath property
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> zbus::Result<Vec<String>>;
+
+    /// Status property
+    #[zbus(property)]
+    fn status(&self) -> zbus::Result<MenuStatus>;
+
+    /// TextDirection property
+    #[zbus(property)]
+    fn text_direction(&self) -> zbus::Result<TextDirection>;
+
+    /// Version property
+    #[zbus(property)]
+    fn version(&self) -> zbus::Result<u32>;
+}
+
+/// A decoded `(ia{sv}av)` menu node with its children.
+#[derive(Debug, Clone, Default)]
+pub struct MenuLayout {
+    pub id: i32,
+    pub property: MenuProperty,
+    pub children: Vec<MenuLayout>,
+}
+
+impl TryFrom<MenuItem> for MenuLayout {
+    type Error = zvariant::Error;
+    fn try_from(value: MenuItem) -> Result<Self, Self::Error> {
+        let mut children = vec![];
+        for sub_menu in value.sub_menus {
+            let sub_menu: MenuItem = sub_menu.try_into()?;
+            children.push(sub_menu.try_into()?);
+        }
+        Ok(MenuLayout {
+            id: *value.id,
+            property: value.property,
+            children,
+        })
+    }
+}
+
+impl MenuLayout {
+    pub fn find(&self, id: i32) -> Option<&MenuLayout> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    pub fn find_mut(&mut self, id: i32) -> Option<&mut MenuLayout> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_mut(id))
+    }
+}
+
+/// A change announced by the menu owner.
+#[derive(Debug, Clone)]
+pub enum MenuUpdate {
+    Layout {
+        revision: u32,
+        parent: i32,
+    },
+    Properties {
+        updated: Vec<(i32, MenuProperty)>,
+        removed: Vec<(i32, Vec<String>)>,
+    },
+}
+
+/// A cached copy of a remote menu, kept in sync through [`MenuUpdate`]s.
+pub struct MenuClient {
+    proxy: DBusMenuProxy<'static>,
+    revision: u32,
+    layout: MenuLayout,
+}
+
+impl MenuClient {
+    /// Fetch the full layout exported by `service` at `path`.
+    pub async fn new(conn: &zbus::Connection, service: &str, path: &str) -> zbus::Result<Self> {
+        let proxy = DBusMenuProxy::builder(conn)
+            .destination(service.to_owned())?
+            .path(path.to_owned())?
+            .build()
+            .await?;
+        let mut client = MenuClient {
+            proxy,
+            revision: 0,
+            layout: MenuLayout::default(),
+        };
+        client.refresh().await?;
+        Ok(client)
+    }
+
+    pub fn proxy(&self) -> &DBusMenuProxy<'static> {
+        &self.proxy
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn layout(&self) -> &MenuLayout {
+        &self.layout
+    }
+
+    /// Refetch the whole menu.
+    pub async fn refresh(&mut self) -> zbus::Result<()> {
+        let (revision, item) = self.proxy.get_layout(0, -1, &[]).await?;
+        self.revision = revision;
+        self.layout = item.try_into()?;
+        Ok(())
+    }
+
+    /// Stream of the layout and property changes of this menu.
+    pub async fn receive_updates(&self) -> zbus::Result<impl Stream<Item = MenuUpdate> + use<>> {
+        let layouts = self
+            .proxy
+            .receive_layout_updated()
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                Some(MenuUpdate::Layout {
+                    revision: args.revision,
+                    parent: args.parent,
+                })
+            });
+        let properties = self
+            .proxy
+            .receive_items_properties_updated()
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                Some(MenuUpdate::Properties {
+                    updated: args.updated_props,
+                    removed: args.removed_props,
+                })
+            });
+        Ok(layouts.or(properties))
+    }
+
+    pub async fn apply(&mut self, update: MenuUpdate) -> zbus::Result<()> {
+        match update {
+            MenuUpdate::Layout { revision, parent } => {
+                self.apply_layout_updated(revision, parent).await
+            }
+            MenuUpdate::Properties { updated, removed } => {
+                self.apply_items_properties_updated(updated, removed);
+                Ok(())
+            }
+        }
+    }
+
+    /// Refetch only the subtree below `parent`.
+    pub async fn apply_layout_updated(&mut self, revision: u32, parent: i32) -> zbus::Result<()> {
+        if self.layout.find(parent).is_none() {
+            return self.refresh().await;
+        }
+        let (new_revision, item) = self.proxy.get_layout(parent, -1, &[]).await?;
+        let subtree: MenuLayout = item.try_into()?;
+        if let Some(node) = self.layout.find_mut(parent) {
+            *node = subtree;
+        }
+        self.revision = new_revision.max(revision);
+        Ok(())
+    }
+
+    pub fn apply_items_properties_updated(
+        &mut self,
+        updated: Vec<(i32, MenuProperty)>,
+        removed: Vec<(i32, Vec<String>)>,
+    ) {
+        for (id, property) in updated {
+            if let Some(node) = self.layout.find_mut(id) {
+                node.property.merge(property);
+            }
+        }
+        for (id, names) in removed {
+            if let Some(node) = self.layout.find_mut(id) {
+                for name in names {
+                    node.property.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// Tell the owner the submenu `id` is about to be shown, refetching it
+    /// when the owner asks for it.
+    pub async fn about_to_show(&mut self, id: i32) -> zbus::Result<()> {
+        if self.proxy.about_to_show(id).await? {
+            self.apply_layout_updated(self.revision, id).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn clicked(&self, id: i32, timestamp: u32) -> zbus::Result<()> {
+        self.proxy
+            .event(id, "clicked", &Value::I32(0), timestamp)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, sync::Arc};
+
+    use futures_lite::future;
+    use zbus::connection;
+
+    use super::*;
+    use crate::dbusmenu::{
+        ButtonOptions, DBusMenuInstance, DBusMenuItem, Id, MenuTree, MenuUnit, SubMenuOptions,
+    };
+
+    const PATH: &str = "/MenuBar";
+
+    struct Static;
+
+    impl DBusMenuItem for Static {
+        type State = ();
+        type Message = ();
+
+        fn boot(&self) {}
+        fn menu(&self) -> MenuTree<()> {
+            MenuTree::new()
+        }
+        fn revision(&self, _state: &()) -> u32 {
+            0
+        }
+    }
+
+    fn labelled(label: &str) -> MenuProperty {
+        MenuProperty {
+            label: Some(label.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn item(id: i32, label: &str) -> MenuItem {
+        MenuItem {
+            id: Id(id),
+            property: labelled(label),
+            sub_menus: vec![],
+        }
+    }
+
+    fn button(label: &str) -> MenuUnit<()> {
+        MenuUnit::button(
+            ButtonOptions {
+                label: label.to_owned(),
+                enabled: true,
+                ..Default::default()
+            },
+            (),
+        )
+    }
+
+    fn sub_menu(children: &[&str]) -> MenuUnit<()> {
+        children.iter().fold(
+            MenuUnit::sub_menu_with_options(SubMenuOptions {
+                label: "sub".to_owned(),
+                ..Default::default()
+            })
+            .with_key("sub"),
+            |sub_menu, label| sub_menu.push(button(label)),
+        )
+    }
+
+    fn labels(layout: &MenuLayout) -> Vec<&str> {
+        layout
+            .children
+            .iter()
+            .map(|child| child.property.label.as_deref().unwrap())
+            .collect()
+    }
+
+    /// Serve `tree` on one end of a socket pair and connect a client to it.
+    async fn connect(tree: MenuTree<()>) -> (zbus::Connection, MenuClient) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let instance = DBusMenuInstance::new(Arc::new(Static), (), tree);
+        let server = connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(PATH, instance)
+            .unwrap()
+            .build();
+        let client = connection::Builder::unix_stream(client).p2p().build();
+        let (server, client) = future::zip(server, client).await;
+        let client = MenuClient::new(&client.unwrap(), "org.example.Menu", PATH)
+            .await
+            .unwrap();
+        (server.unwrap(), client)
+    }
+
+    #[test]
+    fn layout_decodes_nested_items() {
+        let root = item(0, "root")
+            .push_sub_menu(item(1, "a").push_sub_menu(item(3, "c")))
+            .push_sub_menu(item(2, "b"));
+        let layout = MenuLayout::try_from(root).unwrap();
+        assert_eq!(layout.id, 0);
+        assert_eq!(labels(&layout), ["a", "b"]);
+        assert_eq!(layout.children[0].id, 1);
+        assert_eq!(labels(&layout.children[0]), ["c"]);
+        assert_eq!(layout.children[0].children[0].id, 3);
+        assert!(layout.children[1].children.is_empty());
+    }
+
+    #[test]
+    fn find_walks_the_whole_tree() {
+        let root = item(0, "root")
+            .push_sub_menu(item(1, "a").push_sub_menu(item(3, "c")))
+            .push_sub_menu(item(2, "b"));
+        let mut layout = MenuLayout::try_from(root).unwrap();
+        assert_eq!(layout.find(0).unwrap().id, 0);
+        assert_eq!(layout.find(3).unwrap().property.label.as_deref(), Some("c"));
+        assert!(layout.find(4).is_none());
+
+        layout.find_mut(2).unwrap().property.label = Some("d".to_owned());
+        assert_eq!(layout.find(2).unwrap().property.label.as_deref(), Some("d"));
+        assert!(layout.find_mut(4).is_none());
+    }
+
+    #[test]
+    fn property_updates_are_merged_and_removed() {
+        future::block_on(async {
+            let (_server, mut client) = connect(MenuTree::new().push(button("a"))).await;
+            let id = client.layout().children[0].id;
+            let before = client.layout().find(id).unwrap().property.clone();
+            assert_eq!(before.enabled, Some(true));
+
+            client.apply_items_properties_updated(
+                vec![(
+                    id,
+                    MenuProperty {
+                        icon_name: Some("edit".to_owned()),
+                        ..labelled("b")
+                    },
+                )],
+                vec![(id, vec![MenuProperty::ENABLED.to_owned()])],
+            );
+            let after = &client.layout().find(id).unwrap().property;
+            assert_eq!(after.label.as_deref(), Some("b"));
+            assert_eq!(after.icon_name.as_deref(), Some("edit"));
+            assert_eq!(after.enabled, None);
+            assert_eq!(after.visible, before.visible);
+        });
+    }
+
+    #[test]
+    fn layout_updates_replace_the_subtree() {
+        future::block_on(async {
+            let tree = MenuTree::new().push(sub_menu(&["x"])).push(button("other"));
+            let (server, mut client) = connect(tree).await;
+            let sub_id = client.layout().children[0].id;
+            let other_id = client.layout().children[1].id;
+            assert_eq!(labels(&client.layout().children[0]), ["x"]);
+
+            let interface = server
+                .object_server()
+                .interface::<_, DBusMenuInstance<(), ()>>(PATH)
+                .await
+                .unwrap();
+            let revision = {
+                let mut instance = interface.get_mut().await;
+                let tree = MenuTree::new()
+                    .push(sub_menu(&["y", "z"]))
+                    .push(button("renamed"));
+                instance.update_menu_tree(tree);
+                instance.revision()
+            };
+
+            client.apply_layout_updated(revision, sub_id).await.unwrap();
+            let sub = client.layout().find(sub_id).unwrap();
+            assert_eq!(labels(sub), ["y", "z"]);
+            // Only the subtree below the parent is refetched.
+            let other = client.layout().find(other_id).unwrap();
+            assert_eq!(other.property.label.as_deref(), Some("other"));
+            assert_eq!(client.revision(), revision);
+
+            // An unknown parent refetches the whole menu.
+            client.apply_layout_updated(revision, 1000).await.unwrap();
+            let other = client.layout().find(other_id).unwrap();
+            assert_eq!(other.property.label.as_deref(), Some("renamed"));
+        });
+    }
+}
@@ -0,0 +1,428 @@
+//! Comparison of two menu trees, used to tell the hosts only what changed.
+use std::collections::{HashMap, HashSet};
+
+use zbus::object_server::SignalEmitter;
+
+use super::{DBusMenuInstance, IdOrGroup, MenuProperty, MenuTree, MenuUnit};
+
+struct Node {
+    parent: Option<i32>,
+    property: MenuProperty,
+    children: Vec<i32>,
+}
+
+/// The flattened form of a [`MenuTree`], indexed by id.
+pub(crate) struct MenuSnapshot(HashMap<i32, Node>);
+
+impl MenuSnapshot {
+    pub(crate) fn new<Message: Clone>(tree: &MenuTree<Message>) -> Self {
+        let mut nodes = HashMap::new();
+        flatten(tree.get_unit(), None, &mut nodes);
+        MenuSnapshot(nodes)
+    }
+
+    fn is_inside(&self, mut id: i32, subtrees: &HashSet<i32>) -> bool {
+        loop {
+            if subtrees.contains(&id) {
+                return true;
+            }
+            match self.0.get(&id).and_then(|node| node.parent) {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// What changed going from `self` to `new`.
+    pub(crate) fn diff(&self, new: &MenuSnapshot) -> MenuChanges {
+        // Every node whose children were added, removed or reordered
+        let changed: HashSet<i32> = new
+            .0
+            .iter()
+            .filter(|(id, node)| {
+                self.0
+                    .get(id)
+                    .is_some_and(|old| old.children != node.children)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        // Only keep the outermost ones, refetching them covers the rest
+        let mut layouts: Vec<i32> = changed
+            .iter()
+            .filter(|id| {
+                new.0[id]
+                    .parent
+                    .is_none_or(|parent| !new.is_inside(parent, &changed))
+            })
+            .copied()
+            .collect();
+        layouts.sort();
+
+        let layouts_set: HashSet<i32> = layouts.iter().copied().collect();
+        let mut updated = vec![];
+        let mut removed = vec![];
+        for (id, node) in &new.0 {
+            let Some(old) = self.0.get(id) else {
+                continue;
+            };
+            if new.is_inside(*id, &layouts_set) {
+                continue;
+            }
+            let (changed_props, removed_props) = old.property.diff(&node.property);
+            if changed_props != MenuProperty::default() {
+                updated.push((*id, changed_props));
+            }
+            if !removed_props.is_empty() {
+                removed.push((*id, removed_props));
+            }
+        }
+        updated.sort_by_key(|(id, _)| *id);
+        removed.sort_by_key(|(id, _)| *id);
+
+        MenuChanges {
+            layouts,
+            updated,
+            removed,
+        }
+    }
+}
+
+fn flatten<Message: Clone>(
+    unit: &MenuUnit<Message>,
+    parent: Option<i32>,
+    nodes: &mut HashMap<i32, Node>,
+) {
+    let IdOrGroup::Id(id) = unit.id_or_ids() else {
+        return;
+    };
+    let mut children = vec![];
+    for sub_menu in unit.sub_menus().into_iter().flatten() {
+        match sub_menu {
+            MenuUnit::RadioGroup { selections } => {
+                for selection in selections {
+                    if let IdOrGroup::Id(child) = selection.id_or_ids() {
+                        children.push(*child);
+                    }
+                    flatten(selection, Some(*id), nodes);
+                }
+            }
+            _ => {
+                if let IdOrGroup::Id(child) = sub_menu.id_or_ids() {
+                    children.push(*child);
+                }
+                flatten(sub_menu, Some(*id), nodes);
+            }
+        }
+    }
+    nodes.insert(
+        *id,
+        Node {
+            parent,
+            property: unit.get_property().unwrap_or_default(),
+            children,
+        },
+    );
+}
+
+/// The signals needed to bring the hosts up to date.
+#[derive(Debug, Default)]
+pub(crate) struct MenuChanges {
+    /// Roots of the subtrees whose structure changed.
+    pub(crate) layouts: Vec<i32>,
+    pub(crate) updated: Vec<(i32, MenuProperty)>,
+    pub(crate) removed: Vec<(i32, Vec<&'static str>)>,
+}
+
+impl MenuChanges {
+    pub(crate) fn is_layout_changed(&self) -> bool {
+        !self.layouts.is_empty()
+    }
+
+    pub(crate) async fn emit<State, Message>(
+        self,
+        emitter: &SignalEmitter<'_>,
+        revision: u32,
+    ) -> zbus::Result<()>
+    where
+        State: 'static + Send + Sync,
+        Message: 'static + Send + Sync + Clone,
+    {
+        if !self.updated.is_empty() || !self.removed.is_empty() {
+            DBusMenuInstance::<State, Message>::items_properties_updated(
+                emitter,
+                self.updated,
+                self.removed,
+            )
+            .await?;
+        }
+        for parent in self.layouts {
+            DBusMenuInstance::<State, Message>::layout_updated(emitter, revision, parent).await?;
+        }
+        Ok(())
+    }
+}
+
+impl MenuProperty {
+    /// The properties set or changed in `new`, and the names of the ones it unset.
+    pub(crate) fn diff(&self, new: &MenuProperty) -> (MenuProperty, Vec<&'static str>) {
+        use MenuProperty as P;
+        let mut updated = MenuProperty::default();
+        let mut removed = vec![];
+        compare(
+            &self.item_type,
+            &new.item_type,
+            P::TYPE,
+            &mut updated.item_type,
+            &mut removed,
+        );
+        compare(
+            &self.label,
+            &new.label,
+            P::LABEL,
+            &mut updated.label,
+            &mut removed,
+        );
+        compare(
+            &self.icon_name,
+            &new.icon_name,
+            P::ICON_NAME,
+            &mut updated.icon_name,
+            &mut removed,
+        );
+        compare(
+            &self.enabled,
+            &new.enabled,
+            P::ENABLED,
+            &mut updated.enabled,
+            &mut removed,
+        );
+        compare(
+            &self.toggle_type,
+            &new.toggle_type,
+            P::TOGGLE_TYPE,
+            &mut updated.toggle_type,
+            &mut removed,
+        );
+        compare(
+            &self.toggle_state,
+            &new.toggle_state,
+            P::TOGGLE_STATE,
+            &mut updated.toggle_state,
+            &mut removed,
+        );
+        compare(
+            &self.children_display,
+            &new.children_display,
+            P::CHILDREN_DISPLAY,
+            &mut updated.children_display,
+            &mut removed,
+        );
+        compare(
+            &self.visible,
+            &new.visible,
+            P::VISIBLE,
+            &mut updated.visible,
+            &mut removed,
+        );
+        compare(
+            &self.icon_data,
+            &new.icon_data,
+            P::ICON_DATA,
+            &mut updated.icon_data,
+            &mut removed,
+        );
+        compare(
+            &self.shortcut,
+            &new.shortcut,
+            P::SHORTCUT,
+            &mut updated.shortcut,
+            &mut removed,
+        );
+        compare(
+            &self.disposition,
+            &new.disposition,
+            P::DISPOSITION,
+            &mut updated.disposition,
+            &mut removed,
+        );
+        compare(
+            &self.accessible_desc,
+            &new.accessible_desc,
+            P::ACCESSIBLE_DESC,
+            &mut updated.accessible_desc,
+            &mut removed,
+        );
+        (updated, removed)
+    }
+}
+
+fn compare<T: Clone + PartialEq>(
+    old: &Option<T>,
+    new: &Option<T>,
+    name: &'static str,
+    updated: &mut Option<T>,
+    removed: &mut Vec<&'static str>,
+) {
+    if old == new {
+        return;
+    }
+    match new {
+        Some(_) => *updated = new.clone(),
+        None => removed.push(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbusmenu::{ButtonOptions, IdAllocator, MenuItemType};
+
+    fn button(key: &str, label: &str) -> MenuUnit<()> {
+        MenuUnit::button(
+            ButtonOptions {
+                label: label.to_owned(),
+                ..Default::default()
+            },
+            (),
+        )
+        .with_key(key)
+    }
+
+    fn sub_menu(key: &str, children: Vec<MenuUnit<()>>) -> MenuUnit<()> {
+        children.into_iter().fold(
+            MenuUnit::sub_menu(key.to_owned()).with_key(key),
+            |sub_menu, child| sub_menu.push(child),
+        )
+    }
+
+    fn snapshot(ids: &mut IdAllocator, units: Vec<MenuUnit<()>>) -> (MenuTree<()>, MenuSnapshot) {
+        let mut tree = units.into_iter().fold(MenuTree::new(), MenuTree::push);
+        ids.assign(&MenuTree::new(), &mut tree);
+        let snapshot = MenuSnapshot::new(&tree);
+        (tree, snapshot)
+    }
+
+    fn id_of(tree: &MenuTree<()>, label: &str) -> i32 {
+        let item: crate::dbusmenu::MenuItem = tree.into();
+        item.ids()
+            .into_iter()
+            .find(|id| item.find(*id).unwrap().property.label.as_deref() == Some(label))
+            .unwrap()
+    }
+
+    #[test]
+    fn unchanged_trees_have_no_changes() {
+        let mut ids = IdAllocator::default();
+        let units = || vec![button("a", "A"), sub_menu("s", vec![button("b", "B")])];
+        let (_, old) = snapshot(&mut ids, units());
+        let (_, new) = snapshot(&mut ids, units());
+        let changes = old.diff(&new);
+        assert!(changes.layouts.is_empty());
+        assert!(changes.updated.is_empty());
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn property_changes_are_updates() {
+        let mut ids = IdAllocator::default();
+        let (_, old) = snapshot(&mut ids, vec![button("a", "A"), button("b", "B")]);
+        let (tree, new) = snapshot(&mut ids, vec![button("a", "A"), button("b", "C")]);
+        let changes = old.diff(&new);
+        assert!(!changes.is_layout_changed());
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(changes.updated[0].0, id_of(&tree, "C"));
+        assert_eq!(changes.updated[0].1.label.as_deref(), Some("C"));
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn showing_a_hidden_item_unsets_visible() {
+        let item = |hidden| {
+            let options = ButtonOptions {
+                label: "A".to_owned(),
+                hidden,
+                ..Default::default()
+            };
+            MenuUnit::button(options, ()).with_key("a")
+        };
+        let mut ids = IdAllocator::default();
+        let (_, hidden) = snapshot(&mut ids, vec![item(true)]);
+        let (tree, shown) = snapshot(&mut ids, vec![item(false)]);
+        let changes = hidden.diff(&shown);
+        assert!(changes.updated.is_empty());
+        assert_eq!(changes.removed, [(id_of(&tree, "A"), vec!["visible"])]);
+
+        let changes = shown.diff(&hidden);
+        assert_eq!(changes.updated[0].1.visible, Some(false));
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn only_the_outermost_changed_layout_is_reported() {
+        let mut ids = IdAllocator::default();
+        let (_, old) = snapshot(
+            &mut ids,
+            vec![sub_menu("s", vec![sub_menu("t", vec![button("a", "A")])])],
+        );
+        let (tree, new) = snapshot(
+            &mut ids,
+            vec![sub_menu(
+                "s",
+                vec![button("b", "B"), sub_menu("t", vec![button("c", "C")])],
+            )],
+        );
+        let changes = old.diff(&new);
+        assert_eq!(changes.layouts, vec![id_of(&tree, "s")]);
+        assert!(changes.updated.is_empty());
+    }
+
+    #[test]
+    fn reordering_changes_the_parent_layout() {
+        let mut ids = IdAllocator::default();
+        let (_, old) = snapshot(&mut ids, vec![button("a", "A"), button("b", "B")]);
+        let (_, new) = snapshot(&mut ids, vec![button("b", "B"), button("a", "A")]);
+        assert_eq!(old.diff(&new).layouts, vec![0]);
+    }
+
+    #[test]
+    fn property_diff_reports_set_and_unset_properties() {
+        let old = MenuProperty {
+            label: Some("a".to_owned()),
+            icon_name: Some("icon".to_owned()),
+            enabled: Some(true),
+            ..Default::default()
+        };
+        let new = MenuProperty {
+            label: Some("b".to_owned()),
+            enabled: Some(true),
+            visible: Some(false),
+            ..Default::default()
+        };
+        let (updated, removed) = old.diff(&new);
+        assert_eq!(
+            updated,
+            MenuProperty {
+                label: Some("b".to_owned()),
+                visible: Some(false),
+                ..Default::default()
+            }
+        );
+        assert_eq!(removed, vec![MenuProperty::ICON_NAME]);
+    }
+
+    #[test]
+    fn switching_item_type_is_reported() {
+        let mut ids = IdAllocator::default();
+        let (_, old) = snapshot(&mut ids, vec![button("a", "A")]);
+        let (_, new) = snapshot(&mut ids, vec![MenuUnit::separator().with_key("a")]);
+        let changes = old.diff(&new);
+        assert!(!changes.is_layout_changed());
+        let (_, updated) = &changes.updated[0];
+        assert_eq!(updated.item_type, Some(MenuItemType::Separator));
+
+        let (_, back) = snapshot(&mut ids, vec![button("a", "A")]);
+        let changes = new.diff(&back);
+        assert!(changes.removed[0].1.contains(&MenuProperty::TYPE));
+    }
+}
@@ -6,16 +6,55 @@ use zbus::zvariant::{OwnedValue, Type, Value};
     Deserialize, Serialize, Type, PartialEq, Debug, Value, Clone, Copy, OwnedValue, Default,
 )]
 #[zvariant(signature = "s", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum ToggleType {
+    // The variant names were sent before, so still read them
+    #[serde(alias = "Checkmark")]
     Checkmark,
     #[default]
+    #[serde(alias = "Radio")]
     Radio,
     #[zvariant(rename = "")]
+    #[serde(rename = "", alias = "None")]
     None,
 }
 
 #[derive(
-    Deserialize_repr, Serialize_repr, Type, Debug, OwnedValue, Value, Default, Clone, Copy,
+    Deserialize, Serialize, Type, PartialEq, Debug, Value, Clone, Copy, OwnedValue, Default,
+)]
+#[zvariant(signature = "s", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MenuItemType {
+    #[default]
+    Standard,
+    Separator,
+}
+
+/// How an item should be presented, e.g. to draw attention to it.
+#[derive(
+    Deserialize, Serialize, Type, PartialEq, Debug, Value, Clone, Copy, OwnedValue, Default,
+)]
+#[zvariant(signature = "s", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    #[default]
+    Normal,
+    Informative,
+    Warning,
+    Alert,
+}
+
+#[derive(
+    Deserialize_repr,
+    Serialize_repr,
+    Type,
+    PartialEq,
+    Debug,
+    OwnedValue,
+    Value,
+    Default,
+    Clone,
+    Copy,
 )]
 #[repr(i32)]
 pub enum ToggleState {
@@ -32,9 +71,12 @@ pub enum ToggleState {
 pub enum TextDirection {
     #[default]
     #[zvariant(rename = "inherit")]
+    #[serde(rename = "inherit", alias = "Inherit")]
     Inherit,
     #[zvariant(rename = "rtl")]
+    #[serde(rename = "rtl", alias = "Rtl")]
     Rtl,
     #[zvariant(rename = "ltr")]
+    #[serde(rename = "ltr", alias = "Ltr")]
     Ltr,
 }
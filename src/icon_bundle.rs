@@ -0,0 +1,308 @@
+//! Icons shipped inside the binary, served to the hosts as a private icon theme.
+//!
+//! The icons are written into a hicolor layout under `$XDG_RUNTIME_DIR`, which
+//! is then given as the `IconThemePath` of the item and its menu, so they can
+//! be used by name without being installed.
+use std::collections::hash_map::RandomState;
+use std::fs::DirBuilder;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
+
+static NEXT_BUNDLE: AtomicUsize = AtomicUsize::new(1);
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Every bundle gets a directory of its own, removed when its tray shuts down.
+#[derive(Debug)]
+pub struct IconBundle {
+    path: PathBuf,
+    /// The directory shared by the bundles of every process, if any.
+    shared: Option<PathBuf>,
+    icons: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for IconBundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IconBundle {
+    /// Without `$XDG_RUNTIME_DIR`, the icons go to a directory with an
+    /// unpredictable name in the shared temporary directory instead, only
+    /// readable by the user.
+    pub fn new() -> Self {
+        let index = NEXT_BUNDLE.fetch_add(1, atomic::Ordering::Relaxed);
+        let name = format!("{}-{index}", std::process::id());
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => Self::in_runtime_dir(Path::new(&runtime_dir), &name),
+            None => {
+                // Randomly keyed for each process, so others cannot guess it
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write(name.as_bytes());
+                let path = std::env::temp_dir().join(format!(
+                    "libappindicator-zbus-{name}-{:016x}",
+                    hasher.finish()
+                ));
+                IconBundle {
+                    path,
+                    shared: None,
+                    icons: vec![],
+                }
+            }
+        }
+    }
+
+    fn in_runtime_dir(runtime_dir: &Path, name: &str) -> Self {
+        let shared = runtime_dir.join("libappindicator-zbus");
+        IconBundle {
+            path: shared.join(name),
+            shared: Some(shared),
+            icons: vec![],
+        }
+    }
+
+    /// Add the PNG or SVG file `data` as the icon `name`, without extension.
+    ///
+    /// PNGs go to the size they are drawn at, so add one per size. A name
+    /// with a path separator or `..` fails [`Tray::run`](crate::Tray::run).
+    pub fn with_icon(mut self, name: &str, data: impl Into<Vec<u8>>) -> Self {
+        self.icons.push((name.to_owned(), data.into()));
+        self
+    }
+
+    /// The directory given as `IconThemePath`.
+    pub fn theme_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn write(&self) -> io::Result<()> {
+        if let Some((name, _)) = self.icons.iter().find(|(name, _)| !is_valid_name(name)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("icon name {name:?} is not a plain file name"),
+            ));
+        }
+        if let Some(shared) = &self.shared {
+            private_dir().recursive(true).create(shared)?;
+        }
+        // Never write into a directory someone else created in our place
+        if let Err(err) = private_dir().create(&self.path) {
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+            std::fs::remove_dir_all(&self.path)?;
+            private_dir().create(&self.path)?;
+        }
+
+        let theme = self.path.join("hicolor");
+        let mut directories = vec![];
+        for (name, data) in &self.icons {
+            let (directory, file) = match png_size(data) {
+                Some(size) => (format!("{size}x{size}/apps"), format!("{name}.png")),
+                None if is_svg(data) => ("scalable/apps".to_owned(), format!("{name}.svg")),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("icon {name} is neither a PNG nor an SVG file"),
+                    ));
+                }
+            };
+            std::fs::create_dir_all(theme.join(&directory))?;
+            std::fs::write(theme.join(&directory).join(file), data)?;
+            if !directories.contains(&directory) {
+                directories.push(directory);
+            }
+        }
+        directories.sort();
+        std::fs::write(theme.join("index.theme"), index_theme(&directories))
+    }
+
+    pub(crate) fn remove(&self) -> io::Result<()> {
+        match std::fs::remove_dir_all(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        // Only goes away once the last bundle of every process is gone
+        if let Some(shared) = &self.shared {
+            let _ = std::fs::remove_dir(shared);
+        }
+        Ok(())
+    }
+}
+
+fn private_dir() -> DirBuilder {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', '\0']) && !name.contains("..")
+}
+
+/// The larger side of a PNG file, read from its header.
+fn png_size(data: &[u8]) -> Option<u32> {
+    if !data.starts_with(PNG_SIGNATURE) || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+    Some(width.max(height))
+}
+
+fn is_svg(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(1024)];
+    String::from_utf8_lossy(head).contains("<svg")
+}
+
+/// The size of a `<n>x<n>/apps` directory.
+fn fixed_size(directory: &str) -> Option<u32> {
+    let size = directory.strip_suffix("/apps")?;
+    let digits = size.bytes().take_while(u8::is_ascii_digit).count();
+    let (width, height) = size.split_at(digits);
+    if width.is_empty() || height.strip_prefix('x')? != width {
+        return None;
+    }
+    width.parse().ok()
+}
+
+/// Describe `directories`, skipping any not laid out by [`IconBundle::write`].
+fn index_theme(directories: &[String]) -> String {
+    let mut listed = vec![];
+    let mut sections = String::new();
+    for directory in directories {
+        match (directory.as_str(), fixed_size(directory)) {
+            (_, Some(size)) => sections.push_str(&format!(
+                "\n[{directory}]\nSize={size}\nContext=Applications\nType=Fixed\n"
+            )),
+            ("scalable/apps", None) => sections.push_str(&format!(
+                "\n[{directory}]\nSize=64\nMinSize=8\nMaxSize=512\nContext=Applications\nType=Scalable\n"
+            )),
+            _ => continue,
+        }
+        listed.push(directory.as_str());
+    }
+    format!(
+        "[Icon Theme]\nName=hicolor\nComment=Icons bundled with the application\nDirectories={}\n{sections}",
+        listed.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"/>"#;
+
+    /// The header of a `width`x`height` PNG, all the bundle reads of it.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    /// A runtime directory of its own for each test.
+    fn runtime_dir(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "libappindicator-zbus-{test}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn directories(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn only_square_sizes_are_fixed() {
+        assert_eq!(fixed_size("48x48/apps"), Some(48));
+        assert_eq!(fixed_size("128x128/apps"), Some(128));
+        assert_eq!(fixed_size("scalable/apps"), None);
+        assert_eq!(fixed_size("48x32/apps"), None);
+        assert_eq!(fixed_size("x/apps"), None);
+        assert_eq!(fixed_size("48x48"), None);
+        assert_eq!(fixed_size("extra/apps"), None);
+    }
+
+    #[test]
+    fn index_theme_describes_each_directory() {
+        let theme = index_theme(&directories(&["16x16/apps", "scalable/apps", "extra/apps"]));
+        assert!(theme.starts_with("[Icon Theme]\nName=hicolor\n"));
+        assert!(theme.contains("Directories=16x16/apps,scalable/apps\n"));
+        assert!(theme.contains("\n[16x16/apps]\nSize=16\nContext=Applications\nType=Fixed\n"));
+        assert!(theme.contains("\n[scalable/apps]\nSize=64\nMinSize=8\nMaxSize=512\n"));
+        assert!(theme.contains("Type=Scalable\n"));
+        assert!(!theme.contains("[extra/apps]"));
+    }
+
+    #[test]
+    fn bundles_are_laid_out_as_hicolor_and_removed() {
+        let runtime_dir = runtime_dir("layout");
+        let bundle = IconBundle::in_runtime_dir(&runtime_dir, "bundle")
+            .with_icon("app", png(32, 32))
+            .with_icon("app", png(16, 12))
+            .with_icon("app", SVG);
+        let theme = runtime_dir.join("libappindicator-zbus/bundle");
+        assert_eq!(bundle.theme_path(), theme);
+
+        bundle.write().unwrap();
+        let hicolor = theme.join("hicolor");
+        assert_eq!(
+            std::fs::read(hicolor.join("32x32/apps/app.png")).unwrap(),
+            png(32, 32)
+        );
+        assert_eq!(
+            std::fs::read(hicolor.join("16x16/apps/app.png")).unwrap(),
+            png(16, 12)
+        );
+        assert_eq!(
+            std::fs::read(hicolor.join("scalable/apps/app.svg")).unwrap(),
+            SVG
+        );
+        let index = std::fs::read_to_string(hicolor.join("index.theme")).unwrap();
+        assert_eq!(
+            index,
+            index_theme(&directories(&["16x16/apps", "32x32/apps", "scalable/apps"]))
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&theme).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        // What the tray does once it is dropped
+        bundle.remove().unwrap();
+        assert!(!theme.exists());
+        assert!(!runtime_dir.join("libappindicator-zbus").exists());
+        std::fs::remove_dir(&runtime_dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_files_and_names_are_refused() {
+        let runtime_dir = runtime_dir("refused");
+        let bundle = IconBundle::in_runtime_dir(&runtime_dir, "refused").with_icon("../app", SVG);
+        assert_eq!(
+            bundle.write().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        let bundle = IconBundle::in_runtime_dir(&runtime_dir, "refused").with_icon("app", "text");
+        assert_eq!(
+            bundle.write().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        bundle.remove().unwrap();
+        std::fs::remove_dir(&runtime_dir).unwrap();
+    }
+}
@@ -1,16 +1,38 @@
 mod dbusmenu;
+mod icon_bundle;
+mod status_notifier_host;
 mod status_notifier_item;
 mod status_notifier_watcher;
 mod tray;
 
+pub use status_notifier_host::{Host, HostConnection, host};
+pub use status_notifier_watcher::{WatcherConnection, serve_watcher, watcher};
 pub use tray::{Tray, TrayConnection, tray};
 
+pub mod client {
+    pub use crate::dbusmenu::client::{DBusMenuProxy, MenuClient, MenuLayout, MenuUpdate};
+    pub use crate::status_notifier_host::StatusNotifierItemProxy;
+    pub use crate::status_notifier_watcher::StatusNotifierWatcherProxy;
+}
+
 pub mod utils {
     pub use crate::dbusmenu::{
-        ButtonOptions, EventUpdate, MenuItem, MenuProperty, MenuStatus, MenuTree, MenuType,
-        MenuUnit, PropertyItem, RadioGroupBuilder, RadioOptions, TextDirection, ToggleState,
+        ButtonOptions, CheckboxOptions, Disposition, EventUpdate, ItemOptions, MenuItem,
+        MenuItemType, MenuKey, MenuProperty, MenuStatus, MenuTree, MenuType, MenuUnit,
+        PropertyItem, RadioGroupBuilder, RadioOptions, SubMenuOptions, TextDirection, ToggleState,
         ToggleType,
     };
 
-    pub use crate::status_notifier_item::{Category, IconPixmap, NotifierStatus, ToolTip};
+    pub use crate::icon_bundle::IconBundle;
+    pub use crate::status_notifier_host::{HostEvent, HostEventFn, ItemChange, TrayItem};
+    #[cfg(feature = "svg")]
+    pub use crate::status_notifier_item::SvgIcon;
+    #[cfg(feature = "animation")]
+    pub use crate::status_notifier_item::{Animation, AnimationFrame};
+    pub use crate::status_notifier_item::{
+        Badge, BadgeStyle, BadgedPixmaps, Category, IconPixmap, NotifierStatus, ToolTip,
+        escape_markup,
+    };
+    pub use crate::status_notifier_watcher::StatusNotifierWatcherInstance;
+    pub use crate::tray::{TrayAvailability, TrayAvailabilityFn, TrayFallbackFn};
 }
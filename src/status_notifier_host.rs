@@ -0,0 +1,731 @@
+//! # StatusNotifierHost client for consuming `org.kde.StatusNotifierItem`s
+//!
+//! The host registers itself with the `org.kde.StatusNotifierWatcher`, follows
+//! the items it announces and keeps a cache of their properties, refreshed
+//! whenever an item emits one of its `New*` signals.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+
+use futures_lite::StreamExt;
+use zbus::{
+    fdo::{DBusProxy, RequestNameFlags, RequestNameReply},
+    proxy,
+    proxy::CacheProperties,
+    zvariant::OwnedObjectPath,
+};
+
+use crate::{
+    status_notifier_item::{Category, IconPixmap, NotifierStatus, ToolTip},
+    status_notifier_watcher::{
+        StatusNotifierWatcherProxy, WATCHER_SERVICE, is_watcher_missing, split_service,
+    },
+};
+
+#[proxy(
+    interface = "org.kde.StatusNotifierItem",
+    default_path = "/StatusNotifierItem",
+    gen_blocking = false
+)]
+pub trait StatusNotifierItem {
+    /// Activate method
+    fn activate(&self, x: i32, y: i32) -> zbus::Result<()>;
+
+    /// ContextMenu method
+    fn context_menu(&self, x: i32, y: i32) -> zbus::Result<()>;
+
+    /// Scroll method
+    fn scroll(&self, delta: i32, orientation: &str) -> zbus::Result<()>;
+
+    /// SecondaryActivate method
+    fn secondary_activate(&self, x: i32, y: i32) -> zbus::Result<()>;
+
+    /// AttentionIconName property
+    #[zbus(property)]
+    fn attention_icon_name(&self) -> zbus::Result<String>;
+
+    /// AttentionIconPixmap property
+    #[zbus(property)]
+    fn attention_icon_pixmap(&self) -> zbus::Result<Vec<IconPixmap>>;
+
+    /// AttentionMovieName property
+    #[zbus(property)]
+    fn attention_movie_name(&self) -> zbus::Result<String>;
+
+    /// Category property
+    #[zbus(property)]
+    fn category(&self) -> zbus::Result<Category>;
+
+    /// IconName property
+    #[zbus(property)]
+    fn icon_name(&self) -> zbus::Result<String>;
+
+    /// IconPixmap property
+    #[zbus(property)]
+    fn icon_pixmap(&self) -> zbus::Result<Vec<IconPixmap>>;
+
+    /// IconThemePath property
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> zbus::Result<String>;
+
+    /// Id property
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    /// ItemIsMenu property
+    #[zbus(property)]
+    fn item_is_menu(&self) -> zbus::Result<bool>;
+
+    /// Menu property
+    #[zbus(property)]
+    fn menu(&self) -> zbus::Result<OwnedObjectPath>;
+
+    /// OverlayIconName property
+    #[zbus(property)]
+    fn overlay_icon_name(&self) -> zbus::Result<String>;
+
+    /// OverlayIconPixmap property
+    #[zbus(property)]
+    fn overlay_icon_pixmap(&self) -> zbus::Result<Vec<IconPixmap>>;
+
+    /// Status property
+    #[zbus(property)]
+    fn status(&self) -> zbus::Result<NotifierStatus>;
+
+    /// Title property
+    #[zbus(property)]
+    fn title(&self) -> zbus::Result<String>;
+
+    /// ToolTip property
+    #[zbus(property)]
+    fn tool_tip(&self) -> zbus::Result<ToolTip>;
+
+    /// WindowId property
+    #[zbus(property)]
+    fn window_id(&self) -> zbus::Result<i32>;
+}
+
+/// Cached properties of a tray item seen by the host.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrayItem {
+    /// The service string the item was registered with.
+    pub service: String,
+    pub id: String,
+    pub title: String,
+    pub category: Category,
+    pub status: NotifierStatus,
+    pub window_id: i32,
+    pub icon_theme_path: String,
+    pub icon_name: String,
+    pub icon_pixmap: Vec<IconPixmap>,
+    pub overlay_icon_name: String,
+    pub overlay_icon_pixmap: Vec<IconPixmap>,
+    pub attention_icon_name: String,
+    pub attention_icon_pixmap: Vec<IconPixmap>,
+    pub attention_movie_name: String,
+    pub tool_tip: ToolTip,
+    pub item_is_menu: bool,
+    pub menu: Option<OwnedObjectPath>,
+}
+
+/// The group of properties refreshed after an item signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemChange {
+    Icon,
+    AttentionIcon,
+    OverlayIcon,
+    Title,
+    ToolTip,
+    Status,
+    Menu,
+}
+
+impl ItemChange {
+    fn from_signal(member: &str) -> Option<Self> {
+        Some(match member {
+            "NewIcon" => ItemChange::Icon,
+            "NewAttentionIcon" => ItemChange::AttentionIcon,
+            "NewOverlayIcon" => ItemChange::OverlayIcon,
+            "NewTitle" => ItemChange::Title,
+            "NewToolTip" => ItemChange::ToolTip,
+            "NewStatus" => ItemChange::Status,
+            "NewMenu" => ItemChange::Menu,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostEvent {
+    ItemRegistered(String),
+    ItemUnregistered(String),
+    ItemUpdated { service: String, change: ItemChange },
+}
+
+pub trait HostEventFn {
+    fn on_event(&self, event: HostEvent);
+}
+
+impl<T> HostEventFn for T
+where
+    T: Fn(HostEvent),
+{
+    fn on_event(&self, event: HostEvent) {
+        self(event)
+    }
+}
+
+type SharedEventFn = Arc<dyn HostEventFn + Send + Sync>;
+
+#[derive(Default)]
+struct HostInner {
+    items: BTreeMap<String, TrayItem>,
+    tasks: HashMap<String, zbus::Task<()>>,
+}
+
+impl TrayItem {
+    async fn load(proxy: &StatusNotifierItemProxy<'_>, service: String) -> Self {
+        let mut item = TrayItem {
+            service,
+            id: proxy.id().await.unwrap_or_default(),
+            category: proxy.category().await.unwrap_or_default(),
+            window_id: proxy.window_id().await.unwrap_or_default(),
+            item_is_menu: proxy.item_is_menu().await.unwrap_or_default(),
+            ..Default::default()
+        };
+        for change in [
+            ItemChange::Icon,
+            ItemChange::AttentionIcon,
+            ItemChange::OverlayIcon,
+            ItemChange::Title,
+            ItemChange::ToolTip,
+            ItemChange::Status,
+            ItemChange::Menu,
+        ] {
+            item.refresh(proxy, change).await;
+        }
+        item
+    }
+
+    async fn refresh(&mut self, proxy: &StatusNotifierItemProxy<'_>, change: ItemChange) {
+        match change {
+            ItemChange::Icon => {
+                self.icon_theme_path = proxy.icon_theme_path().await.unwrap_or_default();
+                self.icon_name = proxy.icon_name().await.unwrap_or_default();
+                self.icon_pixmap = proxy.icon_pixmap().await.unwrap_or_default();
+            }
+            ItemChange::AttentionIcon => {
+                self.attention_icon_name = proxy.attention_icon_name().await.unwrap_or_default();
+                self.attention_icon_pixmap =
+                    proxy.attention_icon_pixmap().await.unwrap_or_default();
+                self.attention_movie_name = proxy.attention_movie_name().await.unwrap_or_default();
+            }
+            ItemChange::OverlayIcon => {
+                self.overlay_icon_name = proxy.overlay_icon_name().await.unwrap_or_default();
+                self.overlay_icon_pixmap = proxy.overlay_icon_pixmap().await.unwrap_or_default();
+            }
+            ItemChange::Title => {
+                self.title = proxy.title().await.unwrap_or_default();
+            }
+            ItemChange::ToolTip => {
+                self.tool_tip = proxy.tool_tip().await.unwrap_or_default();
+            }
+            ItemChange::Status => {
+                self.status = proxy.status().await.unwrap_or_default();
+            }
+            ItemChange::Menu => {
+                self.menu = proxy.menu().await.ok();
+            }
+        }
+    }
+}
+
+async fn item_proxy(
+    conn: &zbus::Connection,
+    service: &str,
+) -> zbus::Result<StatusNotifierItemProxy<'static>> {
+    let (name, path) = split_service(service, None);
+    StatusNotifierItemProxy::builder(conn)
+        .destination(name.to_owned())?
+        .path(path.to_owned())?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+}
+
+async fn add_item(
+    conn: &zbus::Connection,
+    inner: &Arc<Mutex<HostInner>>,
+    on_event: &SharedEventFn,
+    service: String,
+) {
+    if inner.lock().unwrap().items.contains_key(&service) {
+        return;
+    }
+    let Ok(proxy) = item_proxy(conn, &service).await else {
+        return;
+    };
+    let Ok(mut signals) = proxy.inner().receive_all_signals().await else {
+        return;
+    };
+    let item = TrayItem::load(&proxy, service.clone()).await;
+
+    let weak = Arc::downgrade(inner);
+    let task_on_event = on_event.clone();
+    let task_service = service.clone();
+    let task = conn.executor().spawn(
+        async move {
+            let proxy = proxy;
+            while let Some(message) = signals.next().await {
+                let header = message.header();
+                let Some(change) = header
+                    .member()
+                    .and_then(|member| ItemChange::from_signal(member))
+                else {
+                    continue;
+                };
+                let Some(mut item) = upgrade_item(&weak, &task_service) else {
+                    break;
+                };
+                item.refresh(&proxy, change).await;
+                let Some(inner) = weak.upgrade() else {
+                    break;
+                };
+                inner
+                    .lock()
+                    .unwrap()
+                    .items
+                    .insert(task_service.clone(), item);
+                task_on_event.on_event(HostEvent::ItemUpdated {
+                    service: task_service.clone(),
+                    change,
+                });
+            }
+        },
+        "status notifier host item",
+    );
+    {
+        let mut inner = inner.lock().unwrap();
+        inner.items.insert(service.clone(), item);
+        inner.tasks.insert(service.clone(), task);
+    }
+    on_event.on_event(HostEvent::ItemRegistered(service));
+}
+
+fn upgrade_item(inner: &Weak<Mutex<HostInner>>, service: &str) -> Option<TrayItem> {
+    inner.upgrade()?.lock().unwrap().items.get(service).cloned()
+}
+
+fn remove_item(inner: &Arc<Mutex<HostInner>>, on_event: &SharedEventFn, service: &str) {
+    let removed = {
+        let mut inner = inner.lock().unwrap();
+        inner.tasks.remove(service);
+        inner.items.remove(service).is_some()
+    };
+    if removed {
+        on_event.on_event(HostEvent::ItemUnregistered(service.to_owned()));
+    }
+}
+
+/// Register `name` with the watcher and sync the cache with its item list.
+async fn sync_with_watcher(
+    conn: &zbus::Connection,
+    inner: &Arc<Mutex<HostInner>>,
+    on_event: &SharedEventFn,
+    name: &str,
+) -> zbus::Result<()> {
+    let watcher = StatusNotifierWatcherProxy::builder(conn)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    watcher.register_status_notifier_host(name).await?;
+    let registered = watcher.registered_status_notifier_items().await?;
+    let stale: Vec<String> = inner
+        .lock()
+        .unwrap()
+        .items
+        .keys()
+        .filter(|service| !registered.contains(service))
+        .cloned()
+        .collect();
+    for service in stale {
+        remove_item(inner, on_event, &service);
+    }
+    for service in registered {
+        add_item(conn, inner, on_event, service).await;
+    }
+    Ok(())
+}
+
+enum WatcherEvent {
+    Registered(String),
+    Unregistered(String),
+    WatcherStarted,
+}
+
+pub struct Host {
+    on_event: SharedEventFn,
+    wait_for_watcher: bool,
+}
+
+pub struct HostConnection {
+    conn: zbus::Connection,
+    name: String,
+    inner: Arc<Mutex<HostInner>>,
+    _task: zbus::Task<()>,
+}
+
+/// Create a StatusNotifierHost on the session bus.
+pub fn host() -> Host {
+    Host {
+        on_event: Arc::new(|_event: HostEvent| {}),
+        wait_for_watcher: false,
+    }
+}
+
+impl Host {
+    pub fn with_on_event(self, f: impl HostEventFn + Send + Sync + 'static) -> Host {
+        Host {
+            on_event: Arc::new(f),
+            ..self
+        }
+    }
+
+    /// Start without any item when there is no watcher yet, and sync with
+    /// the first one that appears, instead of failing [`Host::run`].
+    pub fn with_wait_for_watcher(self, wait_for_watcher: bool) -> Host {
+        Host {
+            wait_for_watcher,
+            ..self
+        }
+    }
+
+    /// Register the host on the session bus and load the items of the
+    /// watcher.
+    ///
+    /// Fails when no `org.kde.StatusNotifierWatcher` is running, unless
+    /// [`Host::with_wait_for_watcher`] is set.
+    pub async fn run(self) -> zbus::Result<HostConnection> {
+        let conn = zbus::connection::Builder::session()?.build().await?;
+        let pid = std::process::id();
+        let mut name = format!("org.kde.StatusNotifierHost-{pid}");
+        let mut index = 1;
+        while !matches!(
+            conn.request_name_with_flags(name.as_str(), RequestNameFlags::DoNotQueue.into())
+                .await?,
+            RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner
+        ) {
+            index += 1;
+            name = format!("org.kde.StatusNotifierHost-{pid}-{index}");
+        }
+
+        let watcher = StatusNotifierWatcherProxy::new(&conn).await?;
+        let registered = watcher
+            .receive_status_notifier_item_registered()
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                Some(WatcherEvent::Registered(args.service().to_string()))
+            });
+        let unregistered = watcher
+            .receive_status_notifier_item_unregistered()
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                Some(WatcherEvent::Unregistered(args.service().to_string()))
+            });
+        let restarted = DBusProxy::new(&conn)
+            .await?
+            .receive_name_owner_changed_with_args(&[(0, WATCHER_SERVICE)])
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                args.new_owner()
+                    .is_some()
+                    .then_some(WatcherEvent::WatcherStarted)
+            });
+        let mut events = registered.or(unregistered).or(restarted);
+
+        let inner = Arc::new(Mutex::new(HostInner::default()));
+        match sync_with_watcher(&conn, &inner, &self.on_event, &name).await {
+            Err(err) if !(self.wait_for_watcher && is_watcher_missing(&err)) => return Err(err),
+            _ => {}
+        }
+
+        let task_conn = conn.clone();
+        let task_inner = inner.clone();
+        let task_name = name.clone();
+        let on_event = self.on_event;
+        let task = conn.executor().spawn(
+            async move {
+                while let Some(event) = events.next().await {
+                    match event {
+                        WatcherEvent::Registered(service) => {
+                            add_item(&task_conn, &task_inner, &on_event, service).await;
+                        }
+                        WatcherEvent::Unregistered(service) => {
+                            remove_item(&task_inner, &on_event, &service);
+                        }
+                        WatcherEvent::WatcherStarted => {
+                            let _ =
+                                sync_with_watcher(&task_conn, &task_inner, &on_event, &task_name)
+                                    .await;
+                        }
+                    }
+                }
+            },
+            "status notifier host",
+        );
+
+        Ok(HostConnection {
+            conn,
+            name,
+            inner,
+            _task: task,
+        })
+    }
+}
+
+impl HostConnection {
+    /// The unique bus name of the host connection.
+    pub fn unique_name(&self) -> Option<&zbus::names::OwnedUniqueName> {
+        self.conn.unique_name()
+    }
+
+    /// The `org.kde.StatusNotifierHost-<pid>` name registered with the watcher.
+    pub fn service_name(&self) -> &str {
+        &self.name
+    }
+
+    /// A snapshot of every known item, ordered by service.
+    pub fn items(&self) -> Vec<TrayItem> {
+        self.inner.lock().unwrap().items.values().cloned().collect()
+    }
+
+    /// A snapshot of the item registered as `service`.
+    pub fn item(&self, service: &str) -> Option<TrayItem> {
+        self.inner.lock().unwrap().items.get(service).cloned()
+    }
+
+    /// Ask the item `service` to activate, usually on a left click at `x`, `y`.
+    pub async fn activate(&self, service: &str, x: i32, y: i32) -> zbus::Result<()> {
+        item_proxy(&self.conn, service).await?.activate(x, y).await
+    }
+
+    /// Ask the item `service` for its secondary action, usually on a middle click.
+    pub async fn secondary_activate(&self, service: &str, x: i32, y: i32) -> zbus::Result<()> {
+        item_proxy(&self.conn, service)
+            .await?
+            .secondary_activate(x, y)
+            .await
+    }
+
+    /// Ask the item `service` to show its own context menu at `x`, `y`.
+    pub async fn context_menu(&self, service: &str, x: i32, y: i32) -> zbus::Result<()> {
+        item_proxy(&self.conn, service)
+            .await?
+            .context_menu(x, y)
+            .await
+    }
+
+    /// Forward a scroll of `delta` steps, `orientation` being `vertical` or
+    /// `horizontal`.
+    pub async fn scroll(&self, service: &str, delta: i32, orientation: &str) -> zbus::Result<()> {
+        item_proxy(&self.conn, service)
+            .await?
+            .scroll(delta, orientation)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use futures_lite::future;
+    use zbus::{connection, interface, object_server::SignalEmitter};
+
+    use super::*;
+
+    const SERVICE: &str = "org.example.App/StatusNotifierItem";
+
+    struct Item {
+        title: String,
+        icon_name: String,
+    }
+
+    #[interface(name = "org.kde.StatusNotifierItem")]
+    impl Item {
+        #[zbus(property)]
+        fn id(&self) -> String {
+            "example".to_owned()
+        }
+
+        #[zbus(property)]
+        fn title(&self) -> String {
+            self.title.clone()
+        }
+
+        #[zbus(property)]
+        fn icon_name(&self) -> String {
+            self.icon_name.clone()
+        }
+
+        #[zbus(property)]
+        fn status(&self) -> NotifierStatus {
+            NotifierStatus::NeedsAttention
+        }
+
+        #[zbus(signal)]
+        async fn new_title(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+    }
+
+    struct Fixture {
+        item: zbus::Connection,
+        host: zbus::Connection,
+        inner: Arc<Mutex<HostInner>>,
+        on_event: SharedEventFn,
+        events: mpsc::Receiver<HostEvent>,
+    }
+
+    impl Fixture {
+        /// Serve an item on one end of a socket pair, the host being the other end.
+        async fn new() -> Self {
+            let (item, host) = UnixStream::pair().unwrap();
+            let item = connection::Builder::unix_stream(item)
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at(
+                    "/StatusNotifierItem",
+                    Item {
+                        title: "one".to_owned(),
+                        icon_name: "a".to_owned(),
+                    },
+                )
+                .unwrap()
+                .build();
+            let host = connection::Builder::unix_stream(host).p2p().build();
+            let (item, host) = future::zip(item, host).await;
+            let (sender, events) = mpsc::channel();
+            let sender = Mutex::new(sender);
+            Fixture {
+                item: item.unwrap(),
+                host: host.unwrap(),
+                inner: Arc::default(),
+                on_event: Arc::new(move |event| sender.lock().unwrap().send(event).unwrap()),
+                events,
+            }
+        }
+
+        async fn add(&self) {
+            add_item(&self.host, &self.inner, &self.on_event, SERVICE.to_owned()).await;
+        }
+
+        fn remove(&self) {
+            remove_item(&self.inner, &self.on_event, SERVICE);
+        }
+
+        fn cached(&self) -> Option<TrayItem> {
+            self.inner.lock().unwrap().items.get(SERVICE).cloned()
+        }
+
+        fn next_event(&self) -> Option<HostEvent> {
+            self.events.recv_timeout(Duration::from_secs(5)).ok()
+        }
+    }
+
+    #[test]
+    fn signals_name_the_refreshed_group() {
+        assert_eq!(ItemChange::from_signal("NewIcon"), Some(ItemChange::Icon));
+        assert_eq!(
+            ItemChange::from_signal("NewAttentionIcon"),
+            Some(ItemChange::AttentionIcon)
+        );
+        assert_eq!(
+            ItemChange::from_signal("NewToolTip"),
+            Some(ItemChange::ToolTip)
+        );
+        assert_eq!(ItemChange::from_signal("NewMenu"), Some(ItemChange::Menu));
+        assert_eq!(ItemChange::from_signal("PropertiesChanged"), None);
+    }
+
+    #[test]
+    fn added_items_are_loaded_once() {
+        future::block_on(async {
+            let fixture = Fixture::new().await;
+            fixture.add().await;
+            assert_eq!(
+                fixture.next_event(),
+                Some(HostEvent::ItemRegistered(SERVICE.to_owned()))
+            );
+            let item = fixture.cached().unwrap();
+            assert_eq!(item.service, SERVICE);
+            assert_eq!(item.id, "example");
+            assert_eq!(item.title, "one");
+            assert_eq!(item.icon_name, "a");
+            assert_eq!(item.status, NotifierStatus::NeedsAttention);
+            // Missing properties are left empty.
+            assert!(item.icon_pixmap.is_empty());
+            assert_eq!(item.menu, None);
+
+            fixture.add().await;
+            assert!(fixture.events.try_recv().is_err());
+            assert_eq!(fixture.inner.lock().unwrap().tasks.len(), 1);
+        });
+    }
+
+    #[test]
+    fn signals_refresh_only_their_group() {
+        future::block_on(async {
+            let fixture = Fixture::new().await;
+            fixture.add().await;
+            fixture.next_event();
+
+            let iface = fixture
+                .item
+                .object_server()
+                .interface::<_, Item>("/StatusNotifierItem")
+                .await
+                .unwrap();
+            {
+                let mut item = iface.get_mut().await;
+                item.title = "two".to_owned();
+                item.icon_name = "b".to_owned();
+            }
+            Item::new_title(iface.signal_emitter()).await.unwrap();
+
+            assert_eq!(
+                fixture.next_event(),
+                Some(HostEvent::ItemUpdated {
+                    service: SERVICE.to_owned(),
+                    change: ItemChange::Title,
+                })
+            );
+            let item = fixture.cached().unwrap();
+            assert_eq!(item.title, "two");
+            assert_eq!(item.icon_name, "a");
+        });
+    }
+
+    #[test]
+    fn removed_items_are_dropped_once() {
+        future::block_on(async {
+            let fixture = Fixture::new().await;
+            fixture.add().await;
+            fixture.next_event();
+
+            fixture.remove();
+            assert_eq!(
+                fixture.next_event(),
+                Some(HostEvent::ItemUnregistered(SERVICE.to_owned()))
+            );
+            assert_eq!(fixture.cached(), None);
+            assert!(fixture.inner.lock().unwrap().tasks.is_empty());
+
+            fixture.remove();
+            assert!(fixture.events.try_recv().is_err());
+        });
+    }
+}
@@ -20,13 +20,30 @@
 //! [Writing a client proxy]: https://dbus2.github.io/zbus/client.html
 //! [D-Bus standard interfaces]: https://dbus.freedesktop.org/doc/dbus-specification.html#standard-interfaces,
 
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 use zbus::{
     interface,
     object_server::SignalEmitter,
-    zvariant::{ObjectPath, OwnedValue, Type, Value},
+    zvariant::{OwnedObjectPath, OwnedValue, Type, Value},
 };
 
+#[cfg(feature = "animation")]
+mod animation;
+mod badge;
+mod pixmap;
+#[cfg(feature = "svg")]
+mod svg;
+mod tool_tip;
+
+#[cfg(feature = "animation")]
+pub use animation::{Animation, AnimationFrame};
+pub use badge::{Badge, BadgeStyle, BadgedPixmaps};
+#[cfg(feature = "svg")]
+pub use svg::SvgIcon;
+pub use tool_tip::escape_markup;
+
 #[derive(Clone, PartialEq, Type, OwnedValue, Value, Debug, Default)]
 pub struct IconPixmap {
     pub width: i32,
@@ -51,7 +68,16 @@ pub enum NotifierStatus {
     NeedsAttention,
 }
 
-const MENU_PATH: ObjectPath = ObjectPath::from_static_str_unchecked("/MenuBar");
+impl NotifierStatus {
+    /// The name of the status, as sent with the `NewStatus` signal.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifierStatus::Active => "Active",
+            NotifierStatus::Passive => "Passive",
+            NotifierStatus::NeedsAttention => "NeedsAttention",
+        }
+    }
+}
 
 #[derive(
     Clone, Copy, PartialEq, Type, OwnedValue, Value, Debug, Default, Serialize, Deserialize,
@@ -172,6 +198,77 @@ pub trait StatusNotifierItem {
     }
 }
 
+/// Shared programs, kept around to run a tray again after its shutdown.
+impl<T: StatusNotifierItem + ?Sized> StatusNotifierItem for Arc<T> {
+    type State = T::State;
+
+    fn boot(&self) -> Self::State {
+        (**self).boot()
+    }
+    fn id(&self) -> String {
+        (**self).id()
+    }
+    fn activate(&self, state: &mut Self::State, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        (**self).activate(state, x, y)
+    }
+    fn context_menu(&self, state: &mut Self::State, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        (**self).context_menu(state, x, y)
+    }
+    fn scroll(
+        &self,
+        state: &mut Self::State,
+        delta: i32,
+        orientation: &str,
+    ) -> zbus::fdo::Result<()> {
+        (**self).scroll(state, delta, orientation)
+    }
+    fn secondary_activate(&self, state: &mut Self::State, x: i32, y: i32) -> zbus::fdo::Result<()> {
+        (**self).secondary_activate(state, x, y)
+    }
+    fn tool_tip(&self, state: &Self::State) -> zbus::fdo::Result<ToolTip> {
+        (**self).tool_tip(state)
+    }
+    fn icon_theme_path(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).icon_theme_path(state)
+    }
+    fn icon_name(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).icon_name(state)
+    }
+    fn icon_pixmap(&self, state: &Self::State) -> zbus::fdo::Result<Vec<IconPixmap>> {
+        (**self).icon_pixmap(state)
+    }
+    fn attention_icon_name(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).attention_icon_name(state)
+    }
+    fn attention_icon_pixmap(&self, state: &Self::State) -> zbus::fdo::Result<Vec<IconPixmap>> {
+        (**self).attention_icon_pixmap(state)
+    }
+    fn overlay_icon_name(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).overlay_icon_name(state)
+    }
+    fn overlay_icon_pixmap(&self, state: &Self::State) -> zbus::fdo::Result<Vec<IconPixmap>> {
+        (**self).overlay_icon_pixmap(state)
+    }
+    fn attention_movie_name(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).attention_movie_name(state)
+    }
+    fn category(&self) -> Category {
+        (**self).category()
+    }
+    fn title(&self, state: &Self::State) -> zbus::fdo::Result<String> {
+        (**self).title(state)
+    }
+    fn status(&self, state: &Self::State) -> zbus::fdo::Result<NotifierStatus> {
+        (**self).status(state)
+    }
+    fn item_is_menu(&self, state: &Self::State) -> bool {
+        (**self).item_is_menu(state)
+    }
+    fn window_id(&self, state: &Self::State) -> zbus::fdo::Result<i32> {
+        (**self).window_id(state)
+    }
+}
+
 pub trait NotifierBootFn<State> {
     fn boot(&self) -> State;
 }
@@ -531,8 +628,151 @@ where
 }
 
 pub struct StatusNotifierInstance<State> {
-    pub(crate) program: Box<dyn StatusNotifierItem<State = State> + Send + Sync>,
+    pub(crate) program: Arc<dyn StatusNotifierItem<State = State> + Send + Sync>,
     pub(crate) state: State,
+    pub(crate) menu_path: OwnedObjectPath,
+    /// The frame of the attention animation shown instead of the program's pixmaps.
+    pub(crate) attention_frame: Option<Vec<IconPixmap>>,
+}
+
+/// The values of the properties that can change with the state.
+#[derive(PartialEq)]
+pub(crate) struct ItemSnapshot {
+    icon_theme_path: Option<String>,
+    icon_name: Option<String>,
+    icon_pixmap: Option<Vec<IconPixmap>>,
+    attention_icon_name: Option<String>,
+    attention_icon_pixmap: Option<Vec<IconPixmap>>,
+    attention_movie_name: Option<String>,
+    overlay_icon_name: Option<String>,
+    overlay_icon_pixmap: Option<Vec<IconPixmap>>,
+    title: Option<String>,
+    tool_tip: Option<ToolTip>,
+    status: Option<NotifierStatus>,
+    item_is_menu: bool,
+    window_id: Option<i32>,
+}
+
+impl<State> StatusNotifierInstance<State>
+where
+    State: 'static + Send + Sync,
+{
+    pub(crate) fn snapshot(&self) -> ItemSnapshot {
+        let program = &self.program;
+        let state = &self.state;
+        ItemSnapshot {
+            icon_theme_path: program.icon_theme_path(state).ok(),
+            icon_name: program.icon_name(state).ok(),
+            icon_pixmap: program.icon_pixmap(state).ok(),
+            attention_icon_name: program.attention_icon_name(state).ok(),
+            attention_icon_pixmap: self.current_attention_icon_pixmap().ok(),
+            attention_movie_name: program.attention_movie_name(state).ok(),
+            overlay_icon_name: program.overlay_icon_name(state).ok(),
+            overlay_icon_pixmap: program.overlay_icon_pixmap(state).ok(),
+            title: program.title(state).ok(),
+            tool_tip: program.tool_tip(state).ok(),
+            status: program.status(state).ok(),
+            item_is_menu: program.item_is_menu(state),
+            window_id: program.window_id(state).ok(),
+        }
+    }
+
+    fn current_attention_icon_pixmap(&self) -> zbus::fdo::Result<Vec<IconPixmap>> {
+        match &self.attention_frame {
+            Some(frame) => Ok(frame.clone()),
+            None => self.program.attention_icon_pixmap(&self.state),
+        }
+    }
+
+    /// Show `frame` as the attention icon, or the program's one again for `None`.
+    #[cfg(feature = "animation")]
+    pub(crate) async fn show_attention_frame(
+        &mut self,
+        frame: Option<Vec<IconPixmap>>,
+        emitter: &SignalEmitter<'_>,
+    ) -> zbus::Result<()> {
+        if frame == self.attention_frame {
+            return Ok(());
+        }
+        self.attention_frame = frame;
+        // Back to a program without attention pixmaps, there is no value to send
+        if self.current_attention_icon_pixmap().is_ok() {
+            self.attention_icon_pixmap_changed(emitter).await?;
+        }
+        Self::new_attention_icon(emitter).await
+    }
+
+    /// Emit the `New*` and `PropertiesChanged` signals for what changed
+    /// since `old` was taken.
+    pub(crate) async fn notify_changes(
+        &self,
+        old: &ItemSnapshot,
+        emitter: &SignalEmitter<'_>,
+    ) -> zbus::Result<()> {
+        let new = self.snapshot();
+        if new == *old {
+            return Ok(());
+        }
+        if new.icon_theme_path != old.icon_theme_path {
+            self.icon_theme_path_changed(emitter).await?;
+        }
+        if new.icon_name != old.icon_name || new.icon_pixmap != old.icon_pixmap {
+            if new.icon_name != old.icon_name {
+                self.icon_name_changed(emitter).await?;
+            }
+            if new.icon_pixmap != old.icon_pixmap {
+                self.icon_pixmap_changed(emitter).await?;
+            }
+            Self::new_icon(emitter).await?;
+        }
+        if new.attention_icon_name != old.attention_icon_name
+            || new.attention_icon_pixmap != old.attention_icon_pixmap
+            || new.attention_movie_name != old.attention_movie_name
+        {
+            if new.attention_icon_name != old.attention_icon_name {
+                self.attention_icon_name_changed(emitter).await?;
+            }
+            if new.attention_icon_pixmap != old.attention_icon_pixmap {
+                self.attention_icon_pixmap_changed(emitter).await?;
+            }
+            if new.attention_movie_name != old.attention_movie_name {
+                self.attention_movie_name_changed(emitter).await?;
+            }
+            Self::new_attention_icon(emitter).await?;
+        }
+        if new.overlay_icon_name != old.overlay_icon_name
+            || new.overlay_icon_pixmap != old.overlay_icon_pixmap
+        {
+            if new.overlay_icon_name != old.overlay_icon_name {
+                self.overlay_icon_name_changed(emitter).await?;
+            }
+            if new.overlay_icon_pixmap != old.overlay_icon_pixmap {
+                self.overlay_icon_pixmap_changed(emitter).await?;
+            }
+            Self::new_overlay_icon(emitter).await?;
+        }
+        if new.title != old.title {
+            self.title_changed(emitter).await?;
+            Self::new_title(emitter).await?;
+        }
+        if new.tool_tip != old.tool_tip {
+            self.tool_tip_changed(emitter).await?;
+            Self::new_tool_tip(emitter).await?;
+        }
+        if new.status != old.status {
+            self.status_changed(emitter).await?;
+            if let Some(status) = &new.status {
+                Self::new_status(emitter, status.as_str()).await?;
+            }
+        }
+        if new.item_is_menu != old.item_is_menu {
+            self.item_is_menu_changed(emitter).await?;
+        }
+        if new.window_id != old.window_id {
+            self.window_id_changed(emitter).await?;
+        }
+        Ok(())
+    }
 }
 
 #[interface(name = "org.kde.StatusNotifierItem")]
@@ -559,36 +799,36 @@ where
 
     /// NewAttentionIcon signal
     #[zbus(signal)]
-    async fn new_attention_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_attention_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// NewIcon signal
     #[zbus(signal)]
-    pub async fn new_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// NewMenu signal
     #[zbus(signal)]
-    pub async fn new_menu(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_menu(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// NewOverlayIcon signal
     #[zbus(signal)]
-    pub async fn new_overlay_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_overlay_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// NewStatus signal
     #[zbus(signal)]
-    pub async fn new_status(ctxt: &SignalEmitter<'_>, status: &str) -> zbus::Result<()>;
+    pub(crate) async fn new_status(ctxt: &SignalEmitter<'_>, status: &str) -> zbus::Result<()>;
 
     /// NewTitle signal
     #[zbus(signal)]
-    pub async fn new_title(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_title(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// NewToolTip signal
     #[zbus(signal)]
-    pub async fn new_tool_tip(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn new_tool_tip(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
     /// Menu property
     #[zbus(property)]
-    fn menu(&self) -> zbus::zvariant::OwnedObjectPath {
-        MENU_PATH.into()
+    fn menu(&self) -> OwnedObjectPath {
+        self.menu_path.clone()
     }
 
     /// ToolTip property
@@ -624,7 +864,7 @@ where
     /// AttentionIconPixmap property
     #[zbus(property)]
     fn attention_icon_pixmap(&self) -> zbus::fdo::Result<Vec<IconPixmap>> {
-        self.program.attention_icon_pixmap(&self.state)
+        self.current_attention_icon_pixmap()
     }
 
     /// AttentionMovieName property
@@ -0,0 +1,189 @@
+//! Frames cycled through as the attention icon, since most hosts ignore
+//! `AttentionMovieName`.
+use std::time::Duration;
+
+use zbus::fdo;
+
+use super::IconPixmap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationFrame {
+    pub pixmaps: Vec<IconPixmap>,
+    /// How long the frame stays up.
+    pub delay: Duration,
+}
+
+/// See [`TrayConnection::set_attention_animation`].
+///
+/// [`TrayConnection::set_attention_animation`]: crate::TrayConnection::set_attention_animation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Animation {
+    frames: Vec<AnimationFrame>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_frame(mut self, pixmaps: Vec<IconPixmap>, delay: Duration) -> Self {
+        self.frames.push(AnimationFrame { pixmaps, delay });
+        self
+    }
+
+    pub fn frames(&self) -> &[AnimationFrame] {
+        &self.frames
+    }
+
+    /// Decode an animated GIF file.
+    pub fn from_gif(data: &[u8]) -> fdo::Result<Self> {
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(data))
+            .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        Self::decode(decoder)
+    }
+
+    /// Decode an animated PNG file.
+    pub fn from_apng(data: &[u8]) -> fdo::Result<Self> {
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(data))
+            .and_then(|decoder| decoder.apng())
+            .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        Self::decode(decoder)
+    }
+
+    fn decode<'a>(decoder: impl image::AnimationDecoder<'a>) -> fdo::Result<Self> {
+        // Like browsers, do not let a frame without delay spin the host
+        const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+        let mut animation = Animation::new();
+        for frame in decoder.into_frames() {
+            let frame = frame.map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = match Duration::from_millis(numer as u64) / denom.max(1) {
+                Duration::ZERO => DEFAULT_DELAY,
+                delay => delay,
+            };
+            let buffer = frame.into_buffer();
+            let pixmap = IconPixmap::from_rgba(buffer.width(), buffer.height(), buffer.as_raw())?;
+            animation = animation.with_frame(pixmap.sizes(), delay);
+        }
+        Ok(animation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Delay, Frame, RgbaImage, codecs::gif::GifEncoder};
+
+    use super::*;
+
+    const RED: [u8; 4] = [0xff, 0x00, 0x00, 0xff];
+    const BLUE: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+    const TRANSLUCENT: [u8; 4] = [0x11, 0x22, 0x33, 0x80];
+
+    /// Two pixels wide, so their order is checked too.
+    fn frame(left: [u8; 4], right: [u8; 4]) -> Vec<u8> {
+        [left, right].concat()
+    }
+
+    fn argb([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        [a, r, g, b]
+    }
+
+    fn delays(animation: &Animation) -> Vec<u64> {
+        let frames = animation.frames().iter();
+        frames.map(|frame| frame.delay.as_millis() as u64).collect()
+    }
+
+    fn only_pixmap(frame: &AnimationFrame) -> &IconPixmap {
+        assert_eq!(frame.pixmaps.len(), 1);
+        &frame.pixmaps[0]
+    }
+
+    fn gif(frames: &[(Vec<u8>, u32)]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut encoder = GifEncoder::new(&mut data);
+        for (pixels, delay) in frames {
+            let buffer = RgbaImage::from_raw(2, 1, pixels.clone()).unwrap();
+            let delay = Delay::from_numer_denom_ms(*delay, 1);
+            encoder
+                .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+                .unwrap();
+        }
+        drop(encoder);
+        data
+    }
+
+    /// `delays` as the numerator and denominator of each frame, in seconds.
+    fn apng(frames: &[(Vec<u8>, u16, u16)]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for (pixels, numerator, denominator) in frames {
+            writer.set_frame_delay(*numerator, *denominator).unwrap();
+            writer.write_image_data(pixels).unwrap();
+        }
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn gif_frames_are_converted_to_argb() {
+        let data = gif(&[(frame(RED, BLUE), 50), (frame(BLUE, RED), 250)]);
+        let animation = Animation::from_gif(&data).unwrap();
+        assert_eq!(animation.frames().len(), 2);
+        assert_eq!(delays(&animation), [50, 250]);
+        let first = only_pixmap(&animation.frames()[0]);
+        assert_eq!((first.width, first.height), (2, 1));
+        assert_eq!(first.data, [argb(RED), argb(BLUE)].concat());
+        let second = only_pixmap(&animation.frames()[1]);
+        assert_eq!(second.data, [argb(BLUE), argb(RED)].concat());
+    }
+
+    #[test]
+    fn gif_frames_without_delay_last_100ms() {
+        let data = gif(&[(frame(RED, RED), 0), (frame(BLUE, BLUE), 30)]);
+        let animation = Animation::from_gif(&data).unwrap();
+        assert_eq!(delays(&animation), [100, 30]);
+
+        // A red pixel without any graphic control extension, hence no delay
+        let data = [
+            b"GIF89a".as_slice(),
+            &[1, 0, 1, 0, 0x80, 0, 0],
+            &[0xff, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &[0x2c, 0, 0, 0, 0, 1, 0, 1, 0, 0],
+            &[2, 2, 0x44, 0x01, 0],
+            &[0x3b],
+        ]
+        .concat();
+        let animation = Animation::from_gif(&data).unwrap();
+        assert_eq!(delays(&animation), [100]);
+        assert_eq!(only_pixmap(&animation.frames()[0]).data, argb(RED));
+    }
+
+    #[test]
+    fn apng_frames_are_converted_to_argb() {
+        let data = apng(&[
+            (frame(TRANSLUCENT, RED), 1, 20),
+            (frame(BLUE, TRANSLUCENT), 0, 1),
+            (frame(RED, BLUE), 1, 0),
+        ]);
+        let animation = Animation::from_apng(&data).unwrap();
+        assert_eq!(animation.frames().len(), 3);
+        // No delay plays as fast as allowed, a zero denominator means 1/100 s
+        assert_eq!(delays(&animation), [50, 100, 10]);
+        let first = only_pixmap(&animation.frames()[0]);
+        assert_eq!(first.data, [argb(TRANSLUCENT), argb(RED)].concat());
+        let second = only_pixmap(&animation.frames()[1]);
+        assert_eq!(second.data, [argb(BLUE), argb(TRANSLUCENT)].concat());
+    }
+
+    #[test]
+    fn broken_files_are_refused() {
+        assert!(Animation::from_gif(b"GIF89a").is_err());
+        assert!(Animation::from_apng(b"not a png").is_err());
+        assert!(Animation::from_apng(&gif(&[(frame(RED, RED), 0)])).is_err());
+    }
+}
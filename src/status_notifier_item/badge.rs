@@ -0,0 +1,338 @@
+//! Badges drawn over an icon, such as unread counters.
+//!
+//! Each one comes out twice: painted onto the icon, for hosts ignoring
+//! `OverlayIconPixmap`, and alone, to be served as that overlay.
+use super::IconPixmap;
+
+/// What to draw over the icon.
+///
+/// Counts and dots go in the top right corner, pixmaps in the bottom right
+/// one, where emblems usually are.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Badge {
+    /// A number, shown as `99+` past 99. Nothing is drawn for `0`.
+    Count(u32),
+    Dot,
+    /// Another icon, scaled to half the size of the base one.
+    Pixmap(Vec<IconPixmap>),
+}
+
+/// Colors of [`Badge::Count`] and [`Badge::Dot`], as ARGB bytes like the pixmaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BadgeStyle {
+    pub background: [u8; 4],
+    pub foreground: [u8; 4],
+}
+
+impl Default for BadgeStyle {
+    fn default() -> Self {
+        BadgeStyle {
+            background: [255, 0xe5, 0x39, 0x35],
+            foreground: [255, 255, 255, 255],
+        }
+    }
+}
+
+/// The result of [`Badge::render`], one pixmap per base pixmap in each set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BadgedPixmaps {
+    /// The icon with the badge painted over it, for `IconPixmap`.
+    pub combined: Vec<IconPixmap>,
+    /// The badge alone, for `OverlayIconPixmap`.
+    pub overlay: Vec<IconPixmap>,
+}
+
+impl Badge {
+    pub fn render(&self, base: &[IconPixmap]) -> BadgedPixmaps {
+        self.render_styled(base, BadgeStyle::default())
+    }
+
+    pub fn render_styled(&self, base: &[IconPixmap], style: BadgeStyle) -> BadgedPixmaps {
+        let mut badged = BadgedPixmaps::default();
+        for pixmap in base {
+            let overlay = self.overlay(pixmap.width, pixmap.height, style);
+            let mut combined = pixmap.clone();
+            if combined.data.len() == overlay.data.len() {
+                for (dst, src) in combined
+                    .data
+                    .chunks_exact_mut(4)
+                    .zip(overlay.data.chunks_exact(4))
+                {
+                    blend(dst, [src[0], src[1], src[2], src[3]], 1.);
+                }
+            }
+            badged.combined.push(combined);
+            badged.overlay.push(overlay);
+        }
+        badged
+    }
+
+    /// The badge alone, on a transparent pixmap of the given size.
+    pub fn overlay(&self, width: i32, height: i32, style: BadgeStyle) -> IconPixmap {
+        let mut canvas = Canvas::new(width.max(0) as usize, height.max(0) as usize);
+        let size = canvas.width.min(canvas.height) as f32;
+        match self {
+            Badge::Count(0) => {}
+            Badge::Count(count) => {
+                let text = if *count > 99 {
+                    "99+".to_owned()
+                } else {
+                    count.to_string()
+                };
+                let pill_height = (size * 0.55).max(7.);
+                let scale = ((pill_height * 0.6 / GLYPH_HEIGHT as f32) as usize).max(1);
+                let text_width = text.len() * (GLYPH_WIDTH + 1) * scale - scale;
+                let text_height = GLYPH_HEIGHT * scale;
+                let pill_width = (text_width as f32 + pill_height * 0.5).max(pill_height);
+                let right = canvas.width as f32;
+                canvas.fill_pill(right - pill_width, 0., right, pill_height, style.background);
+                let left = (right - pill_width / 2. - text_width as f32 / 2.).round() as usize;
+                let top = ((pill_height - text_height as f32) / 2.).round() as usize;
+                canvas.draw_text(&text, left, top, scale, style.foreground);
+            }
+            Badge::Dot => {
+                let diameter = (size * 0.4).max(4.);
+                let right = canvas.width as f32;
+                canvas.fill_pill(right - diameter, 0., right, diameter, style.background);
+            }
+            Badge::Pixmap(pixmaps) => {
+                let target = (size / 2.).round().max(1.) as i32;
+                let Some(closest) = pixmaps.iter().min_by_key(|pixmap| {
+                    let side = pixmap.width.max(pixmap.height);
+                    // Prefer scaling down a larger one over blowing up a smaller one
+                    if side >= target {
+                        side - target
+                    } else {
+                        (target - side) * 4
+                    }
+                }) else {
+                    return canvas.into_pixmap();
+                };
+                let emblem = closest.scaled(target as u32);
+                let left = canvas.width.saturating_sub(emblem.width as usize);
+                let top = canvas.height.saturating_sub(emblem.height as usize);
+                canvas.draw_pixmap(&emblem, left, top);
+            }
+        }
+        canvas.into_pixmap()
+    }
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    fn into_pixmap(self) -> IconPixmap {
+        IconPixmap {
+            width: self.width as i32,
+            height: self.height as i32,
+            data: self.data,
+        }
+    }
+
+    fn paint(&mut self, x: usize, y: usize, color: [u8; 4], coverage: f32) {
+        if x < self.width && y < self.height && coverage > 0. {
+            let i = (y * self.width + x) * 4;
+            blend(&mut self.data[i..i + 4], color, coverage);
+        }
+    }
+
+    /// A rectangle with fully rounded ends, antialiased by sampling each pixel 4x4 times.
+    fn fill_pill(&mut self, left: f32, top: f32, right: f32, bottom: f32, color: [u8; 4]) {
+        let radius = (bottom - top).min(right - left) / 2.;
+        let (cx0, cx1) = (left + radius, right - radius);
+        let (cy0, cy1) = (top + radius, bottom - radius);
+        let inside = |x: f32, y: f32| {
+            let dx = x - x.clamp(cx0, cx1);
+            let dy = y - y.clamp(cy0, cy1);
+            dx * dx + dy * dy <= radius * radius
+        };
+        for y in top.max(0.) as usize..(bottom.ceil() as usize).min(self.height) {
+            for x in left.max(0.) as usize..(right.ceil() as usize).min(self.width) {
+                let mut hits = 0;
+                for sy in 0..4 {
+                    for sx in 0..4 {
+                        let px = x as f32 + (sx as f32 + 0.5) / 4.;
+                        let py = y as f32 + (sy as f32 + 0.5) / 4.;
+                        if inside(px, py) {
+                            hits += 1;
+                        }
+                    }
+                }
+                self.paint(x, y, color, hits as f32 / 16.);
+            }
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, left: usize, top: usize, scale: usize, color: [u8; 4]) {
+        for (index, c) in text.chars().enumerate() {
+            let Some(rows) = glyph(c) else {
+                continue;
+            };
+            let glyph_left = left + index * (GLYPH_WIDTH + 1) * scale;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let x = glyph_left + column * scale + dx;
+                            let y = top + row * scale + dy;
+                            self.paint(x, y, color, 1.);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_pixmap(&mut self, pixmap: &IconPixmap, left: usize, top: usize) {
+        let width = pixmap.width.max(0) as usize;
+        for (index, pixel) in pixmap.data.chunks_exact(4).enumerate() {
+            let (x, y) = (left + index % width, top + index / width);
+            self.paint(x, y, [pixel[0], pixel[1], pixel[2], pixel[3]], 1.);
+        }
+    }
+}
+
+/// Paint `color` over the ARGB pixel `dst`.
+fn blend(dst: &mut [u8], color: [u8; 4], coverage: f32) {
+    let src_a = color[0] as f32 / 255. * coverage;
+    if src_a <= 0. {
+        return;
+    }
+    let dst_a = dst[0] as f32 / 255.;
+    let out_a = src_a + dst_a * (1. - src_a);
+    dst[0] = (out_a * 255.).round() as u8;
+    for channel in 1..4 {
+        let src = color[channel] as f32;
+        let old = dst[channel] as f32;
+        dst[channel] = ((src * src_a + old * dst_a * (1. - src_a)) / out_a).round() as u8;
+    }
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Rows of a 3x5 pixel font, the highest bit being the left column.
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    Some(match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: i32, height: i32, pixel: [u8; 4]) -> IconPixmap {
+        IconPixmap {
+            width,
+            height,
+            data: pixel.repeat((width.max(0) * height.max(0)) as usize),
+        }
+    }
+
+    fn pixel(pixmap: &IconPixmap, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * pixmap.width as usize + x) * 4;
+        pixmap.data[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn overlays_match_their_base() {
+        let base = [solid(16, 16, [255; 4]), solid(48, 24, [255; 4])];
+        for badge in [
+            Badge::Count(7),
+            Badge::Dot,
+            Badge::Pixmap(vec![solid(8, 8, [255; 4])]),
+        ] {
+            let badged = badge.render(&base);
+            for (i, base) in base.iter().enumerate() {
+                for pixmap in [&badged.combined[i], &badged.overlay[i]] {
+                    assert_eq!((pixmap.width, pixmap.height), (base.width, base.height));
+                    assert_eq!(pixmap.data.len(), base.data.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn badges_stay_inside_tiny_and_empty_pixmaps() {
+        let base = [
+            solid(0, 0, [255; 4]),
+            solid(1, 1, [255; 4]),
+            solid(3, 2, [255; 4]),
+            solid(-4, 4, [255; 4]),
+        ];
+        let emblem = Badge::Pixmap(vec![solid(64, 64, [255; 4]), solid(1, 1, [255; 4])]);
+        for badge in [Badge::Count(1000), Badge::Dot, emblem] {
+            let badged = badge.render(&base);
+            assert_eq!(badged.combined.len(), base.len());
+            for pixmap in badged.combined.iter().chain(&badged.overlay) {
+                let (width, height) = (pixmap.width.max(0), pixmap.height.max(0));
+                assert_eq!(pixmap.data.len(), (width * height * 4) as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_go_top_right_and_emblems_bottom_right() {
+        let style = BadgeStyle::default();
+        let count = Badge::Count(3).overlay(32, 32, style);
+        assert_eq!(pixel(&count, 0, 31)[0], 0);
+        assert_ne!(pixel(&count, 28, 3)[0], 0);
+
+        let emblem = Badge::Pixmap(vec![solid(16, 16, [255, 1, 2, 3])]).overlay(32, 32, style);
+        assert_eq!(pixel(&emblem, 31, 31), [255, 1, 2, 3]);
+        assert_eq!(pixel(&emblem, 15, 15), [0; 4]);
+        assert_eq!(pixel(&emblem, 16, 16), [255, 1, 2, 3]);
+    }
+
+    #[test]
+    fn only_the_badge_covers_the_icon() {
+        let base = solid(32, 32, [255, 10, 20, 30]);
+        let badged = Badge::Dot.render(std::slice::from_ref(&base));
+        let combined = &badged.combined[0];
+        assert_eq!(pixel(combined, 0, 31), [255, 10, 20, 30]);
+        assert_eq!(pixel(combined, 25, 6), BadgeStyle::default().background);
+        assert_eq!(
+            Badge::Count(0).render(std::slice::from_ref(&base)).combined[0],
+            base
+        );
+    }
+
+    #[test]
+    fn broken_base_pixmaps_are_left_alone() {
+        let broken = IconPixmap {
+            width: 8,
+            height: 8,
+            data: vec![255; 12],
+        };
+        let badged = Badge::Dot.render(std::slice::from_ref(&broken));
+        assert_eq!(badged.combined[0], broken);
+        assert_eq!(badged.overlay[0].data.len(), 8 * 8 * 4);
+    }
+}
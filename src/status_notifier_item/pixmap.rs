@@ -0,0 +1,241 @@
+//! Conversion of raw images into the ARGB32 pixmaps hosts expect.
+//!
+//! The spec wants every pixel as alpha, red, green and blue bytes, in network
+//! byte order, which is not what image libraries usually hand out.
+use zbus::fdo;
+
+use super::IconPixmap;
+
+impl IconPixmap {
+    /// The sizes [`IconPixmap::sizes`] renders, the usual panel icon sizes.
+    pub const SIZES: [u32; 6] = [16, 22, 24, 32, 48, 64];
+
+    /// Take pixels already in ARGB32, network byte order.
+    pub fn from_argb(width: u32, height: u32, data: &[u8]) -> fdo::Result<Self> {
+        Self::convert(width, height, data, |[a, r, g, b]| [a, r, g, b])
+    }
+
+    /// Take pixels as red, green, blue and alpha bytes, like most image libraries.
+    pub fn from_rgba(width: u32, height: u32, data: &[u8]) -> fdo::Result<Self> {
+        Self::convert(width, height, data, |[r, g, b, a]| [a, r, g, b])
+    }
+
+    /// Take pixels as blue, green, red and alpha bytes, like cairo and most
+    /// native ARGB32 buffers on little endian machines.
+    pub fn from_bgra(width: u32, height: u32, data: &[u8]) -> fdo::Result<Self> {
+        Self::convert(width, height, data, |[b, g, r, a]| [a, r, g, b])
+    }
+
+    #[cfg(feature = "image")]
+    pub fn from_image(image: &image::DynamicImage) -> fdo::Result<Self> {
+        let rgba = image.to_rgba8();
+        Self::from_rgba(rgba.width(), rgba.height(), rgba.as_raw())
+    }
+
+    /// Decode a PNG file.
+    #[cfg(feature = "image")]
+    pub fn from_png(data: &[u8]) -> fdo::Result<Self> {
+        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png)
+            .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        Self::from_image(&image)
+    }
+
+    fn convert(
+        width: u32,
+        height: u32,
+        data: &[u8],
+        pixel: impl Fn([u8; 4]) -> [u8; 4],
+    ) -> fdo::Result<Self> {
+        let (Ok(w), Ok(h)) = (i32::try_from(width), i32::try_from(height)) else {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{width}x{height} is too large for a pixmap"
+            )));
+        };
+        if width as usize * height as usize * 4 != data.len() {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{width}x{height} pixels need {} bytes, got {}",
+                width as usize * height as usize * 4,
+                data.len()
+            )));
+        }
+        let data = data
+            .chunks_exact(4)
+            .flat_map(|chunk| pixel([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        Ok(IconPixmap {
+            width: w,
+            height: h,
+            data,
+        })
+    }
+
+    /// Downscale to each of [`IconPixmap::SIZES`] no larger than the pixmap,
+    /// letting the host pick the one fitting its panel.
+    ///
+    /// A pixmap smaller than all of them is returned as is.
+    pub fn sizes(&self) -> Vec<IconPixmap> {
+        let largest = self.width.max(self.height).max(0) as u32;
+        let sizes: Vec<IconPixmap> = Self::SIZES
+            .iter()
+            .filter(|size| **size <= largest)
+            .map(|size| self.scaled(*size))
+            .collect();
+        if sizes.is_empty() {
+            return vec![self.clone()];
+        }
+        sizes
+    }
+
+    /// Scale so that the longest side is `size`, keeping the aspect ratio.
+    ///
+    /// Each pixel is the average of the ones it covers, weighted by their
+    /// alpha so transparent pixels do not darken the edges.
+    pub fn scaled(&self, size: u32) -> IconPixmap {
+        let (src_w, src_h) = (self.width.max(0) as usize, self.height.max(0) as usize);
+        if src_w == 0 || src_h == 0 || size == 0 || self.data.len() < src_w * src_h * 4 {
+            return IconPixmap::default();
+        }
+        let size = size as usize;
+        let (dst_w, dst_h) = if src_w >= src_h {
+            (size, (src_h * size / src_w).max(1))
+        } else {
+            ((src_w * size / src_h).max(1), size)
+        };
+
+        let mut data = Vec::with_capacity(dst_w * dst_h * 4);
+        for y in 0..dst_h {
+            let (top, bottom) = span(y, dst_h, src_h);
+            for x in 0..dst_w {
+                let (left, right) = span(x, dst_w, src_w);
+                let mut sum = [0u64; 4];
+                for sy in top..bottom {
+                    for sx in left..right {
+                        let i = (sy * src_w + sx) * 4;
+                        let [a, r, g, b] = [
+                            self.data[i],
+                            self.data[i + 1],
+                            self.data[i + 2],
+                            self.data[i + 3],
+                        ]
+                        .map(u64::from);
+                        sum[0] += a;
+                        sum[1] += r * a;
+                        sum[2] += g * a;
+                        sum[3] += b * a;
+                    }
+                }
+                let count = ((bottom - top) * (right - left)) as u64;
+                let [a, r, g, b] = sum;
+                if a == 0 {
+                    data.extend([0; 4]);
+                } else {
+                    data.extend([
+                        (a / count) as u8,
+                        (r / a) as u8,
+                        (g / a) as u8,
+                        (b / a) as u8,
+                    ]);
+                }
+            }
+        }
+        IconPixmap {
+            width: dst_w as i32,
+            height: dst_h as i32,
+            data,
+        }
+    }
+}
+
+/// The source pixels covered by the destination pixel `index`, never empty.
+fn span(index: usize, dst: usize, src: usize) -> (usize, usize) {
+    let start = index * src / dst;
+    let end = ((index + 1) * src / dst).max(start + 1);
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(size: i32, pixel: [u8; 4]) -> IconPixmap {
+        IconPixmap {
+            width: size,
+            height: size,
+            data: pixel.repeat((size * size) as usize),
+        }
+    }
+
+    #[test]
+    fn byte_orders_end_up_as_argb() {
+        let argb = [0x80, 0x11, 0x22, 0x33];
+        assert_eq!(IconPixmap::from_argb(1, 1, &argb).unwrap().data, argb);
+        let rgba = IconPixmap::from_rgba(1, 1, &[0x11, 0x22, 0x33, 0x80]).unwrap();
+        assert_eq!(rgba.data, argb);
+        let bgra = IconPixmap::from_bgra(1, 1, &[0x33, 0x22, 0x11, 0x80]).unwrap();
+        assert_eq!(bgra.data, argb);
+        assert_eq!((bgra.width, bgra.height), (1, 1));
+    }
+
+    #[test]
+    fn data_must_match_the_size() {
+        assert!(IconPixmap::from_rgba(2, 2, &[0; 12]).is_err());
+        assert!(IconPixmap::from_rgba(2, 2, &[0; 20]).is_err());
+        assert!(IconPixmap::from_rgba(u32::MAX, 1, &[]).is_err());
+        assert!(IconPixmap::from_rgba(0, 0, &[]).is_ok());
+    }
+
+    #[test]
+    fn scaling_keeps_the_aspect_ratio() {
+        let wide = IconPixmap {
+            width: 64,
+            height: 32,
+            data: vec![0xff; 64 * 32 * 4],
+        };
+        let scaled = wide.scaled(16);
+        assert_eq!((scaled.width, scaled.height), (16, 8));
+        assert_eq!(scaled.data.len(), 16 * 8 * 4);
+
+        let thin = IconPixmap {
+            width: 1,
+            height: 64,
+            data: vec![0xff; 64 * 4],
+        };
+        assert_eq!((thin.scaled(16).width, thin.scaled(16).height), (1, 16));
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_darken_the_average() {
+        // Opaque red next to fully transparent black
+        let pixmap = IconPixmap {
+            width: 2,
+            height: 1,
+            data: vec![0xff, 0xff, 0, 0, 0, 0, 0, 0],
+        };
+        assert_eq!(pixmap.scaled(1).data, [0x7f, 0xff, 0, 0]);
+        assert_eq!(solid(4, [0; 4]).scaled(2).data, [0; 16]);
+    }
+
+    #[test]
+    fn broken_pixmaps_scale_to_nothing() {
+        let short = IconPixmap {
+            width: 4,
+            height: 4,
+            data: vec![0; 4],
+        };
+        assert_eq!(short.scaled(2), IconPixmap::default());
+        assert_eq!(solid(4, [0xff; 4]).scaled(0), IconPixmap::default());
+    }
+
+    #[test]
+    fn sizes_stop_at_the_pixmap_size() {
+        let sizes: Vec<_> = solid(32, [0xff; 4])
+            .sizes()
+            .iter()
+            .map(|pixmap| pixmap.width)
+            .collect();
+        assert_eq!(sizes, [16, 22, 24, 32]);
+
+        let tiny = solid(8, [0xff; 4]);
+        assert_eq!(tiny.sizes(), std::slice::from_ref(&tiny));
+    }
+}
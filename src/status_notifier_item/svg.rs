@@ -0,0 +1,247 @@
+//! Rendering of SVG icons into pixmaps, for hosts not sharing the app's icon theme.
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use resvg::{tiny_skia, usvg};
+use zbus::fdo;
+
+use super::{AttentionIconPixmapFn, IconPixmap, IconPixmapFn, OverlayIconPixmapFn};
+
+enum Source {
+    Data(Arc<[u8]>),
+    Path(PathBuf),
+}
+
+struct Rendered {
+    /// When and how large the file was, `None` for data which never changes.
+    modified: Option<(SystemTime, u64)>,
+    sizes: Vec<u32>,
+    pixmaps: Vec<IconPixmap>,
+}
+
+/// An SVG icon, rendered into pixmaps on demand.
+///
+/// The last rendering is kept and handed out again until the source or the
+/// requested sizes change. Clones share it, so the same icon can back the
+/// icon, attention icon and tooltip pixmaps. It can be passed as is to
+/// `with_icon_pixmap`, `with_attention_icon_pixmap` and
+/// `with_overlay_icon_pixmap`, while tooltips take [`SvgIcon::pixmaps`].
+///
+/// Text is not rendered, convert it to paths first.
+#[derive(Clone)]
+pub struct SvgIcon {
+    source: Arc<Source>,
+    rendered: Arc<Mutex<Option<Rendered>>>,
+}
+
+impl std::fmt::Debug for SvgIcon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("SvgIcon");
+        match &*self.source {
+            Source::Data(data) => debug.field("len", &data.len()),
+            Source::Path(path) => debug.field("path", path),
+        };
+        debug.finish_non_exhaustive()
+    }
+}
+
+impl SvgIcon {
+    pub fn from_data(data: impl Into<Arc<[u8]>>) -> Self {
+        Self::new(Source::Data(data.into()))
+    }
+
+    /// The file is read again once it is modified, so changes to it show up.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        Self::new(Source::Path(path.into()))
+    }
+
+    fn new(source: Source) -> Self {
+        SvgIcon {
+            source: Arc::new(source),
+            rendered: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Render at each of [`IconPixmap::SIZES`].
+    pub fn pixmaps(&self) -> fdo::Result<Vec<IconPixmap>> {
+        self.pixmaps_with_sizes(&IconPixmap::SIZES)
+    }
+
+    /// Render so that the longest side is each of `sizes`.
+    pub fn pixmaps_with_sizes(&self, sizes: &[u32]) -> fdo::Result<Vec<IconPixmap>> {
+        let not_found = |path: &PathBuf, err: std::io::Error| {
+            fdo::Error::FileNotFound(format!("{}: {err}", path.display()))
+        };
+        let modified = match &*self.source {
+            Source::Data(_) => None,
+            Source::Path(path) => {
+                let metadata = std::fs::metadata(path).map_err(|err| not_found(path, err))?;
+                let modified = metadata.modified().map_err(|err| not_found(path, err))?;
+                Some((modified, metadata.len()))
+            }
+        };
+
+        let mut rendered = self.rendered.lock().unwrap();
+        if let Some(rendered) = rendered.as_ref()
+            && rendered.modified == modified
+            && rendered.sizes == sizes
+        {
+            return Ok(rendered.pixmaps.clone());
+        }
+
+        let data = match &*self.source {
+            Source::Data(data) => data.clone(),
+            Source::Path(path) => std::fs::read(path)
+                .map_err(|err| not_found(path, err))?
+                .into(),
+        };
+
+        let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+            .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        let pixmaps = sizes
+            .iter()
+            .map(|size| render(&tree, *size))
+            .collect::<fdo::Result<Vec<_>>>()?;
+        *rendered = Some(Rendered {
+            modified,
+            sizes: sizes.to_vec(),
+            pixmaps: pixmaps.clone(),
+        });
+        Ok(pixmaps)
+    }
+}
+
+impl<State> IconPixmapFn<State> for SvgIcon {
+    fn icon_pixmap(&self, _state: &State) -> fdo::Result<Vec<IconPixmap>> {
+        self.pixmaps()
+    }
+}
+
+impl<State> AttentionIconPixmapFn<State> for SvgIcon {
+    fn attention_icon_pixmap(&self, _state: &State) -> fdo::Result<Vec<IconPixmap>> {
+        self.pixmaps()
+    }
+}
+
+impl<State> OverlayIconPixmapFn<State> for SvgIcon {
+    fn overlay_icon_pixmap(&self, _state: &State) -> fdo::Result<Vec<IconPixmap>> {
+        self.pixmaps()
+    }
+}
+
+fn render(tree: &usvg::Tree, size: u32) -> fdo::Result<IconPixmap> {
+    let tree_size = tree.size();
+    let scale = size as f32 / tree_size.width().max(tree_size.height());
+    let width = ((tree_size.width() * scale).round() as u32).max(1);
+    let height = ((tree_size.height() * scale).round() as u32).max(1);
+    let Some(mut pixmap) = tiny_skia::Pixmap::new(width, height) else {
+        return Err(fdo::Error::InvalidArgs(format!(
+            "cannot render at {width}x{height}"
+        )));
+    };
+    resvg::render(
+        tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.alpha(), color.red(), color.green(), color.blue()]
+        })
+        .collect();
+    IconPixmap::from_argb(width, height, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// A 2:1 rectangle, red on the left half and blue on the right one.
+    fn flag(left: &str) -> String {
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+                <rect width="10" height="10" fill="{left}"/>
+                <rect x="10" width="10" height="10" fill="#0000ff"/>
+            </svg>"##
+        )
+    }
+
+    fn pixel(pixmap: &IconPixmap, x: i32, y: i32) -> &[u8] {
+        let start = ((y * pixmap.width + x) * 4) as usize;
+        &pixmap.data[start..start + 4]
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "libappindicator-zbus-{}-{name}.svg",
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn set_modified(path: &PathBuf, modified: SystemTime) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn data_is_rendered_as_argb_at_each_size() {
+        let icon = SvgIcon::from_data(flag("#ff0000").into_bytes());
+        let pixmaps = icon.pixmaps_with_sizes(&[16, 32]).unwrap();
+        let sizes: Vec<_> = pixmaps.iter().map(|p| (p.width, p.height)).collect();
+        assert_eq!(sizes, [(16, 8), (32, 16)]);
+        for pixmap in &pixmaps {
+            assert_eq!(
+                pixmap.data.len(),
+                (pixmap.width * pixmap.height * 4) as usize
+            );
+            assert_eq!(pixel(pixmap, 0, 0), [0xff, 0xff, 0x00, 0x00]);
+            assert_eq!(pixel(pixmap, pixmap.width - 1, 0), [0xff, 0x00, 0x00, 0xff]);
+        }
+    }
+
+    #[test]
+    fn one_pixmap_per_size() {
+        let icon = SvgIcon::from_data(flag("#ff0000").into_bytes());
+        let widths: Vec<_> = icon.pixmaps().unwrap().iter().map(|p| p.width).collect();
+        assert_eq!(widths, IconPixmap::SIZES.map(|size| size as i32));
+        assert_eq!(icon.pixmaps_with_sizes(&[24]).unwrap().len(), 1);
+        assert!(icon.pixmaps_with_sizes(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn files_are_rendered_again_once_changed() {
+        let path = temp_file("changed", &flag("#ff0000"));
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        set_modified(&path, modified);
+        let icon = SvgIcon::from_path(&path);
+        let left =
+            |icon: &SvgIcon| pixel(&icon.pixmaps_with_sizes(&[16]).unwrap()[0], 0, 0).to_vec();
+        assert_eq!(left(&icon), [0xff, 0xff, 0x00, 0x00]);
+
+        // Same length and time: the last rendering is still used.
+        std::fs::write(&path, flag("#00ff00")).unwrap();
+        set_modified(&path, modified);
+        assert_eq!(left(&icon), [0xff, 0xff, 0x00, 0x00]);
+
+        // A new modification time alone is enough.
+        set_modified(&path, modified + Duration::from_secs(1));
+        assert_eq!(left(&icon), [0xff, 0x00, 0xff, 0x00]);
+
+        // So is a new length, even with the time left as it was.
+        std::fs::write(&path, flag("white")).unwrap();
+        set_modified(&path, modified + Duration::from_secs(1));
+        assert_eq!(left(&icon), [0xff, 0xff, 0xff, 0xff]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(icon.pixmaps().is_err());
+    }
+}
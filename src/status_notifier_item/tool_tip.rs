@@ -0,0 +1,479 @@
+//! Building tooltips whose description is valid markup.
+//!
+//! The specification lets the description use a small subset of HTML, which
+//! hosts like KDE render as rich text, so a stray `&` or `<` coming from user
+//! content breaks the whole tooltip.
+use zbus::fdo;
+
+use super::{IconPixmap, ToolTip};
+
+/// The tags the specification allows, with the attributes each one takes.
+const TAGS: &[(&str, &[&str])] = &[
+    ("b", &[]),
+    ("i", &[]),
+    ("u", &[]),
+    ("a", &["href"]),
+    ("img", &["src", "alt"]),
+];
+
+/// The attributes holding URLs, with the schemes they may use.
+const URL_SCHEMES: &[(&str, &[&str])] = &[
+    ("href", &["http", "https", "mailto"]),
+    ("src", &["http", "https"]),
+];
+
+const ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+];
+
+/// Escape `text` so it shows as is in markup, for mixing user content into
+/// [`ToolTip::with_markup`].
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl ToolTip {
+    /// The title is plain text, as markup is only allowed in the description.
+    pub fn new(title: impl Into<String>) -> Self {
+        ToolTip {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_icon_name(mut self, icon: impl Into<String>) -> Self {
+        self.icon = icon.into();
+        self
+    }
+
+    pub fn with_pixmaps(mut self, pixmaps: Vec<IconPixmap>) -> Self {
+        self.data = pixmaps;
+        self
+    }
+
+    /// Set the description to plain text, escaped to show as is.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.description = escape_markup(&text.into());
+        self
+    }
+
+    /// Set the description to markup, which may only use `<b>`, `<i>`, `<u>`,
+    /// `<a href>`, `<img src alt/>` and the XML entities.
+    ///
+    /// Links may only be `http`, `https` or `mailto` URLs and images `http`
+    /// or `https` ones, so a tooltip cannot run scripts or point at local files.
+    ///
+    /// Anything else, or markup that is not well formed, is an `InvalidArgs` error.
+    pub fn with_markup(mut self, markup: &str) -> fdo::Result<Self> {
+        self.description = clean_markup(markup, true).map_err(fdo::Error::InvalidArgs)?;
+        Ok(self)
+    }
+
+    /// Like [`ToolTip::with_markup`], but fixing the markup instead of failing.
+    ///
+    /// Unsupported tags and attributes, including URLs with another scheme,
+    /// are dropped while keeping their text, stray `<` and `&` are escaped and
+    /// open tags get closed.
+    pub fn with_sanitized_markup(mut self, markup: &str) -> Self {
+        self.description = clean_markup(markup, false).expect("sanitizing markup does not fail");
+        self
+    }
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+/// Fail with `message` when `strict`, otherwise go on fixing the markup.
+fn fix(strict: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if strict { Err(message()) } else { Ok(()) }
+}
+
+fn clean_markup(markup: &str, strict: bool) -> Result<String, String> {
+    let mut cleaned = String::with_capacity(markup.len());
+    let mut open: Vec<&'static str> = vec![];
+    let mut rest = markup;
+    while let Some(c) = rest.chars().next() {
+        if c != '<' {
+            let end = rest.find('<').unwrap_or(rest.len());
+            clean_text(&rest[..end], strict, &mut cleaned)?;
+            rest = &rest[end..];
+            continue;
+        }
+        let Some((tag, len)) = parse_tag(rest) else {
+            fix(strict, || {
+                let offset = markup.len() - rest.len();
+                format!("malformed tag at byte {offset}")
+            })?;
+            cleaned.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[len..];
+
+        let Some(&(name, allowed)) = TAGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(tag.name))
+        else {
+            fix(strict, || format!("<{}> is not supported", tag.name))?;
+            continue;
+        };
+        if tag.closing {
+            let Some(position) = open.iter().rposition(|open| *open == name) else {
+                fix(strict, || format!("</{name}> closes no <{name}>"))?;
+                continue;
+            };
+            if position + 1 != open.len() {
+                fix(strict, || format!("</{name}> closes <{name}> too early"))?;
+            }
+            // Close what was opened inside, so the tags stay nested
+            for name in open.drain(position..).rev() {
+                cleaned.push_str(&format!("</{name}>"));
+            }
+            continue;
+        }
+
+        let void = name == "img";
+        if tag.self_closing != void {
+            fix(strict, || {
+                if void {
+                    format!("<{name}> must be self-closing")
+                } else {
+                    format!("<{name}> cannot be self-closing")
+                }
+            })?;
+            if !void {
+                continue;
+            }
+        }
+        cleaned.push('<');
+        cleaned.push_str(name);
+        for (key, value) in tag.attributes {
+            let Some(key) = allowed.iter().find(|name| name.eq_ignore_ascii_case(key)) else {
+                fix(strict, || format!("<{name}> does not take {key}"))?;
+                continue;
+            };
+            if let Some((_, schemes)) = URL_SCHEMES.iter().find(|(url, _)| url == key)
+                && !has_scheme(value, schemes)
+            {
+                fix(strict, || {
+                    format!("{key} of <{name}> takes only {} URLs", schemes.join(", "))
+                })?;
+                continue;
+            }
+            cleaned.push_str(&format!(" {key}=\""));
+            clean_text(&value.replace('"', "&quot;"), strict, &mut cleaned)?;
+            cleaned.push('"');
+        }
+        if void {
+            cleaned.push_str("/>");
+        } else {
+            cleaned.push('>');
+            open.push(name);
+        }
+    }
+    if let Some(name) = open.last() {
+        fix(strict, || format!("<{name}> is never closed"))?;
+    }
+    for name in open.into_iter().rev() {
+        cleaned.push_str(&format!("</{name}>"));
+    }
+    Ok(cleaned)
+}
+
+/// Copy text without tags, checking its entities.
+fn clean_text(text: &str, strict: bool, cleaned: &mut String) -> Result<(), String> {
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        cleaned.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match entity_len(rest) {
+            Some(len) => {
+                cleaned.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+            None => {
+                fix(strict, || "& must start an entity such as &amp;".to_owned())?;
+                cleaned.push_str("&amp;");
+                rest = &rest[1..];
+            }
+        }
+    }
+    cleaned.push_str(rest);
+    Ok(())
+}
+
+/// Whether the URL `value` uses one of `schemes`, looking through the
+/// entities and blanks that could hide another one.
+fn has_scheme(value: &str, schemes: &[&str]) -> bool {
+    let mut url = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        let len = match entity(rest) {
+            Some((c, len)) => {
+                url.push(c);
+                len
+            }
+            None => {
+                url.push(c);
+                c.len_utf8()
+            }
+        };
+        rest = &rest[len..];
+    }
+    url.retain(|c| !c.is_ascii_whitespace() && !c.is_control());
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    schemes
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+}
+
+/// The length of the entity `input` starts with, including `&` and `;`.
+fn entity_len(input: &str) -> Option<usize> {
+    entity(input).map(|(_, len)| len)
+}
+
+/// The character the entity `input` starts with stands for, with its length.
+fn entity(input: &str) -> Option<(char, usize)> {
+    let end = input.strip_prefix('&')?.find(';')? + 1;
+    let name = &input[1..end];
+    let c = match name.strip_prefix('#') {
+        Some(code) => {
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => code.parse(),
+            };
+            code.ok().and_then(char::from_u32)?
+        }
+        None => ENTITIES.iter().find(|(entity, _)| *entity == name)?.1,
+    };
+    Some((c, end + 1))
+}
+
+/// Parse the tag `input` starts with, returning it with its length.
+fn parse_tag(input: &str) -> Option<(Tag<'_>, usize)> {
+    // A `>` inside a quoted attribute value does not end the tag
+    let mut quote = None;
+    let end = input.find(|c: char| {
+        match quote {
+            Some(open) if c == open => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => return c == '>',
+            Some(_) => {}
+        }
+        false
+    })?;
+    let inner = &input[1..end];
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    let (self_closing, inner) = match inner.strip_suffix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+    let name_end = inner
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let mut attributes = vec![];
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let key = key.trim_end();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, after) = value[1..].split_once(quote)?;
+        if value.contains('<') {
+            return None;
+        }
+        attributes.push((key, value));
+        rest = after.trim_start();
+    }
+    if closing && (self_closing || !attributes.is_empty()) {
+        return None;
+    }
+    Some((
+        Tag {
+            name,
+            closing,
+            self_closing,
+            attributes,
+        },
+        end + 1,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict(markup: &str) -> Result<String, String> {
+        clean_markup(markup, true)
+    }
+
+    fn sanitized(markup: &str) -> String {
+        clean_markup(markup, false).unwrap()
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let tool_tip = ToolTip::new("title").with_text("a < b & c");
+        assert_eq!(tool_tip.description, "a &lt; b &amp; c");
+        let tool_tip = ToolTip::new("title").with_text(format!("{} items", 3));
+        assert_eq!(tool_tip.description, "3 items");
+    }
+
+    #[test]
+    fn valid_markup_is_kept() {
+        let markup = r#"<b>1</b> &amp; <i>2 <u>3</u></i> <a href="https://a.b/?c=d&amp;e">f</a><img src="http://g/h.png" alt="i"/>"#;
+        assert_eq!(strict(markup).unwrap(), markup);
+        assert_eq!(strict("<B>x</b>").unwrap(), "<b>x</b>");
+        assert_eq!(
+            strict("<a href='x:y'>").unwrap_err(),
+            "href of <a> takes only http, https, mailto URLs"
+        );
+    }
+
+    #[test]
+    fn invalid_markup_fails_when_strict() {
+        for markup in [
+            "<script>x</script>",
+            "<b>x",
+            "x</b>",
+            "<b><i>x</b></i>",
+            "<b/>",
+            "<img src=\"http://a\">",
+            "<b onclick=\"x\">x</b>",
+            "1 < 2",
+            "a & b",
+            "&nosuch;",
+        ] {
+            assert!(strict(markup).is_err(), "{markup}");
+        }
+    }
+
+    #[test]
+    fn sanitizing_fixes_the_markup() {
+        assert_eq!(sanitized("<script>x</script>"), "x");
+        assert_eq!(sanitized("<b>x"), "<b>x</b>");
+        assert_eq!(sanitized("x</b>"), "x");
+        assert_eq!(sanitized("<b><i>x</b>y</i>"), "<b><i>x</i></b>y");
+        assert_eq!(sanitized("<b onclick=\"x\">y</b>"), "<b>y</b>");
+        assert_eq!(sanitized("1 < 2 & 3"), "1 &lt; 2 &amp; 3");
+        assert_eq!(sanitized("<b/>x"), "x");
+        assert_eq!(
+            sanitized("<img src=\"http://a\">"),
+            "<img src=\"http://a\"/>"
+        );
+    }
+
+    #[test]
+    fn only_allowed_url_schemes_pass() {
+        assert!(strict(r#"<a href="mailto:a@b">x</a>"#).is_ok());
+        assert!(strict(r#"<a href="HTTPS://a">x</a>"#).is_ok());
+        for markup in [
+            r#"<a href="javascript:alert(1)">x</a>"#,
+            r#"<a href=" java&#x09;script:alert(1)">x</a>"#,
+            r#"<a href="&#106;avascript:alert(1)">x</a>"#,
+            r#"<a href="relative/path">x</a>"#,
+            r#"<img src="file:///etc/passwd"/>"#,
+            r#"<img src="mailto:a@b"/>"#,
+        ] {
+            assert!(strict(markup).is_err(), "{markup}");
+        }
+        assert_eq!(sanitized(r#"<a href="javascript:x">y</a>"#), "<a>y</a>");
+        assert_eq!(
+            sanitized(r#"<img src="file:///x" alt="y"/>"#),
+            r#"<img alt="y"/>"#
+        );
+    }
+
+    #[test]
+    fn entity_lengths() {
+        assert_eq!(entity_len("&amp;rest"), Some(5));
+        assert_eq!(entity_len("&apos;"), Some(6));
+        assert_eq!(entity_len("&#65;"), Some(5));
+        assert_eq!(entity_len("&#x1F600;"), Some(9));
+        assert_eq!(entity_len("&#xD800;"), None);
+        assert_eq!(entity_len("&#;"), None);
+        assert_eq!(entity_len("&nbsp;"), None);
+        assert_eq!(entity_len("&amp"), None);
+        assert_eq!(entity_len("&"), None);
+    }
+
+    #[test]
+    fn tags_are_parsed_with_their_length() {
+        let (tag, len) = parse_tag("<a href=\"x\">y</a>").unwrap();
+        assert_eq!(
+            (tag.name, tag.closing, tag.self_closing, len),
+            ("a", false, false, 12)
+        );
+        assert_eq!(tag.attributes, [("href", "x")]);
+
+        let (tag, len) = parse_tag("<img  src='a' alt = \"b\" />").unwrap();
+        assert!(tag.self_closing);
+        assert_eq!(tag.attributes, [("src", "a"), ("alt", "b")]);
+        assert_eq!(len, 26);
+
+        let (tag, _) = parse_tag("</b>").unwrap();
+        assert!(tag.closing);
+    }
+
+    #[test]
+    fn quoted_values_may_hold_a_closing_bracket() {
+        let (tag, len) = parse_tag("<a href=\"a>b\">c</a>").unwrap();
+        assert_eq!(tag.attributes, [("href", "a>b")]);
+        assert_eq!(len, 14);
+        let (tag, _) = parse_tag("<img alt='\"1 > 0\"'/>").unwrap();
+        assert_eq!(tag.attributes, [("alt", "\"1 > 0\"")]);
+        assert_eq!(
+            strict("<a href=\"https://a/?b>c\">d</a>").unwrap(),
+            "<a href=\"https://a/?b>c\">d</a>"
+        );
+    }
+
+    #[test]
+    fn malformed_tags_are_not_parsed() {
+        for tag in [
+            "<b",
+            "<>",
+            "< b>",
+            "<b-c>",
+            "<a href=\"x>",
+            "<a href=x>",
+            "<a href>",
+            "</b x=\"y\">",
+            "</b/>",
+            "<a href=\"<b>\">",
+        ] {
+            assert!(parse_tag(tag).is_none(), "{tag}");
+        }
+    }
+}
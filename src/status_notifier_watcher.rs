@@ -19,7 +19,19 @@
 //!
 //! [Writing a client proxy]: https://dbus2.github.io/zbus/client.html
 //! [D-Bus standard interfaces]: https://dbus.freedesktop.org/doc/dbus-specification.html#standard-interfaces,
-use zbus::proxy;
+use futures_lite::StreamExt;
+use zbus::{
+    fdo::{DBusProxy, RequestNameFlags, RequestNameReply},
+    interface,
+    message::Header,
+    object_server::SignalEmitter,
+    proxy,
+};
+
+pub(crate) const WATCHER_SERVICE: &str = "org.kde.StatusNotifierWatcher";
+pub(crate) const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+
 #[proxy(
     interface = "org.kde.StatusNotifierWatcher",
     default_service = "org.kde.StatusNotifierWatcher",
@@ -60,3 +72,355 @@ pub trait StatusNotifierWatcher {
     #[zbus(property)]
     fn registered_status_notifier_items(&self) -> zbus::Result<Vec<String>>;
 }
+
+/// Split a registered item into its bus name and object path.
+///
+/// Accepts a bare bus name (`:1.42`, `org.kde.StatusNotifierItem-1-1`), the
+/// `service/path` notation, or a bare object path, in which case `sender` is
+/// used as the bus name.
+pub(crate) fn split_service<'a>(service: &'a str, sender: Option<&'a str>) -> (&'a str, &'a str) {
+    if service.starts_with('/') {
+        return (sender.unwrap_or_default(), service);
+    }
+    match service.find('/') {
+        Some(index) => (&service[..index], &service[index..]),
+        None => (service, DEFAULT_ITEM_PATH),
+    }
+}
+
+/// Whether a call failed only because no watcher owns its name yet.
+pub(crate) fn is_watcher_missing(err: &zbus::Error) -> bool {
+    match err {
+        zbus::Error::MethodError(name, _, _) => matches!(
+            name.as_str(),
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+                | "org.freedesktop.DBus.Error.NameHasNoOwner"
+        ),
+        zbus::Error::FDO(err) => matches!(
+            **err,
+            zbus::fdo::Error::ServiceUnknown(_) | zbus::fdo::Error::NameHasNoOwner(_)
+        ),
+        _ => false,
+    }
+}
+
+/// Server side implementation of `org.kde.StatusNotifierWatcher`.
+///
+/// Serve it with [`watcher`] or [`serve_watcher`] when no desktop component
+/// provides one.
+#[derive(Debug, Default)]
+pub struct StatusNotifierWatcherInstance {
+    items: Vec<String>,
+    hosts: Vec<String>,
+}
+
+impl StatusNotifierWatcherInstance {
+    /// Drop every item and host owned by `name`, returning what was removed.
+    fn remove_owner(&mut self, name: &str) -> (Vec<String>, Vec<String>) {
+        let (removed_items, items) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| split_service(item, None).0 == name);
+        self.items = items;
+        let (removed_hosts, hosts) = std::mem::take(&mut self.hosts)
+            .into_iter()
+            .partition(|host| host == name);
+        self.hosts = hosts;
+        (removed_items, removed_hosts)
+    }
+}
+
+#[interface(name = "org.kde.StatusNotifierWatcher")]
+impl StatusNotifierWatcherInstance {
+    /// RegisterStatusNotifierItem method
+    async fn register_status_notifier_item(
+        &mut self,
+        service: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let sender = header.sender().map(|sender| sender.as_str());
+        let (name, path) = split_service(service, sender);
+        if name.is_empty() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "Invalid service {service}"
+            )));
+        }
+        let item = format!("{name}{path}");
+        if self.items.contains(&item) {
+            return Ok(());
+        }
+        self.items.push(item.clone());
+        Self::status_notifier_item_registered(&emitter, &item).await?;
+        self.registered_status_notifier_items_changed(&emitter)
+            .await?;
+        Ok(())
+    }
+
+    /// RegisterStatusNotifierHost method
+    async fn register_status_notifier_host(
+        &mut self,
+        service: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let host = match header.sender() {
+            Some(sender) if service.is_empty() || service.starts_with('/') => sender.to_string(),
+            _ => service.to_owned(),
+        };
+        if self.hosts.contains(&host) {
+            return Ok(());
+        }
+        self.hosts.push(host);
+        Self::status_notifier_host_registered(&emitter).await?;
+        if self.hosts.len() == 1 {
+            self.is_status_notifier_host_registered_changed(&emitter)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// StatusNotifierHostRegistered signal
+    #[zbus(signal)]
+    async fn status_notifier_host_registered(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// StatusNotifierHostUnregistered signal
+    #[zbus(signal)]
+    async fn status_notifier_host_unregistered(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// StatusNotifierItemRegistered signal
+    #[zbus(signal)]
+    async fn status_notifier_item_registered(
+        emitter: &SignalEmitter<'_>,
+        service: &str,
+    ) -> zbus::Result<()>;
+
+    /// StatusNotifierItemUnregistered signal
+    #[zbus(signal)]
+    async fn status_notifier_item_unregistered(
+        emitter: &SignalEmitter<'_>,
+        service: &str,
+    ) -> zbus::Result<()>;
+
+    /// IsStatusNotifierHostRegistered property
+    #[zbus(property)]
+    fn is_status_notifier_host_registered(&self) -> bool {
+        !self.hosts.is_empty()
+    }
+
+    /// ProtocolVersion property
+    #[zbus(property)]
+    fn protocol_version(&self) -> i32 {
+        0
+    }
+
+    /// RegisteredStatusNotifierItems property
+    #[zbus(property)]
+    fn registered_status_notifier_items(&self) -> Vec<String> {
+        self.items.clone()
+    }
+}
+
+/// Serve a [`StatusNotifierWatcherInstance`] on `conn` and take the
+/// `org.kde.StatusNotifierWatcher` name.
+///
+/// Items and hosts are dropped again when their bus name vanishes.
+pub async fn serve_watcher(conn: &zbus::Connection) -> zbus::Result<()> {
+    conn.object_server()
+        .at(WATCHER_PATH, StatusNotifierWatcherInstance::default())
+        .await?;
+    let mut owner_changed = DBusProxy::new(conn)
+        .await?
+        .receive_name_owner_changed()
+        .await?;
+    let reply = conn
+        .request_name_with_flags(WATCHER_SERVICE, RequestNameFlags::DoNotQueue.into())
+        .await?;
+    if !matches!(
+        reply,
+        RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner
+    ) {
+        conn.object_server()
+            .remove::<StatusNotifierWatcherInstance, _>(WATCHER_PATH)
+            .await?;
+        return Err(zbus::Error::NameTaken);
+    }
+
+    let iface_ref = conn
+        .object_server()
+        .interface::<_, StatusNotifierWatcherInstance>(WATCHER_PATH)
+        .await?;
+    conn.executor()
+        .spawn(
+            async move {
+                while let Some(signal) = owner_changed.next().await {
+                    let Ok(args) = signal.args() else {
+                        continue;
+                    };
+                    if args.new_owner().is_some() {
+                        continue;
+                    }
+                    let emitter = iface_ref.signal_emitter();
+                    let mut watcher = iface_ref.get_mut().await;
+                    let (items, hosts) = watcher.remove_owner(args.name());
+                    for item in items.iter() {
+                        let _ = StatusNotifierWatcherInstance::status_notifier_item_unregistered(
+                            emitter, item,
+                        )
+                        .await;
+                    }
+                    if !items.is_empty() {
+                        let _ = watcher
+                            .registered_status_notifier_items_changed(emitter)
+                            .await;
+                    }
+                    for _ in hosts.iter() {
+                        let _ = StatusNotifierWatcherInstance::status_notifier_host_unregistered(
+                            emitter,
+                        )
+                        .await;
+                    }
+                    if !hosts.is_empty() && watcher.hosts.is_empty() {
+                        let _ = watcher
+                            .is_status_notifier_host_registered_changed(emitter)
+                            .await;
+                    }
+                }
+            },
+            "status notifier watcher owner tracking",
+        )
+        .detach();
+    Ok(())
+}
+
+pub struct WatcherConnection {
+    conn: zbus::Connection,
+}
+
+impl WatcherConnection {
+    /// The unique bus name of the connection serving the watcher.
+    pub fn unique_name(&self) -> Option<&zbus::names::OwnedUniqueName> {
+        self.conn.unique_name()
+    }
+
+    /// The items currently registered, as `bus name` followed by `object path`.
+    pub async fn registered_items(&self) -> zbus::Result<Vec<String>> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierWatcherInstance>(WATCHER_PATH)
+            .await?;
+        Ok(iface_ref.get().await.items.clone())
+    }
+
+    /// Whether at least one host is registered to show the items.
+    pub async fn is_host_registered(&self) -> zbus::Result<bool> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierWatcherInstance>(WATCHER_PATH)
+            .await?;
+        Ok(!iface_ref.get().await.hosts.is_empty())
+    }
+}
+
+/// Run a standalone `org.kde.StatusNotifierWatcher` on the session bus.
+pub async fn watcher() -> zbus::Result<WatcherConnection> {
+    let conn = zbus::connection::Builder::session()?.build().await?;
+    serve_watcher(&conn).await?;
+    Ok(WatcherConnection { conn })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watcher(items: &[&str], hosts: &[&str]) -> StatusNotifierWatcherInstance {
+        StatusNotifierWatcherInstance {
+            items: items.iter().map(|item| item.to_string()).collect(),
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn bus_names_get_the_default_path() {
+        assert_eq!(split_service(":1.42", None), (":1.42", DEFAULT_ITEM_PATH));
+        assert_eq!(
+            split_service("org.kde.StatusNotifierItem-1-1", Some(":1.7")),
+            ("org.kde.StatusNotifierItem-1-1", DEFAULT_ITEM_PATH)
+        );
+    }
+
+    #[test]
+    fn paths_after_the_bus_name_are_kept() {
+        assert_eq!(
+            split_service(":1.42/org/ayatana/NotificationItem/app", None),
+            (":1.42", "/org/ayatana/NotificationItem/app")
+        );
+        assert_eq!(
+            split_service("org.example.App/StatusNotifierItem", Some(":1.7")),
+            ("org.example.App", "/StatusNotifierItem")
+        );
+    }
+
+    #[test]
+    fn bare_paths_belong_to_the_sender() {
+        assert_eq!(
+            split_service("/org/ayatana/NotificationItem/app", Some(":1.7")),
+            (":1.7", "/org/ayatana/NotificationItem/app")
+        );
+        assert_eq!(
+            split_service("/StatusNotifierItem", None),
+            ("", "/StatusNotifierItem")
+        );
+    }
+
+    #[test]
+    fn vanished_owners_lose_all_their_items() {
+        let mut watcher = watcher(
+            &[
+                ":1.7/StatusNotifierItem",
+                ":1.8/StatusNotifierItem",
+                ":1.7/org/ayatana/NotificationItem/app",
+                ":1.70/StatusNotifierItem",
+            ],
+            &[":1.9"],
+        );
+        let (items, hosts) = watcher.remove_owner(":1.7");
+        assert_eq!(
+            items,
+            [
+                ":1.7/StatusNotifierItem",
+                ":1.7/org/ayatana/NotificationItem/app"
+            ]
+        );
+        assert!(hosts.is_empty());
+        assert_eq!(
+            watcher.items,
+            [":1.8/StatusNotifierItem", ":1.70/StatusNotifierItem"]
+        );
+        assert_eq!(watcher.hosts, [":1.9"]);
+    }
+
+    #[test]
+    fn vanished_hosts_are_unregistered() {
+        let mut watcher = watcher(&[":1.8/StatusNotifierItem"], &[":1.9", ":1.10"]);
+        let (items, hosts) = watcher.remove_owner(":1.9");
+        assert!(items.is_empty());
+        assert_eq!(hosts, [":1.9"]);
+        assert_eq!(watcher.hosts, [":1.10"]);
+        assert!(watcher.is_status_notifier_host_registered());
+
+        watcher.remove_owner(":1.10");
+        assert!(!watcher.is_status_notifier_host_registered());
+        assert_eq!(watcher.items, [":1.8/StatusNotifierItem"]);
+    }
+
+    #[test]
+    fn unknown_owners_change_nothing() {
+        let mut watcher = watcher(&["org.example.App/StatusNotifierItem"], &[":1.9"]);
+        let (items, hosts) = watcher.remove_owner(":1.1");
+        assert!(items.is_empty() && hosts.is_empty());
+        assert_eq!(watcher.items.len(), 1);
+        assert_eq!(watcher.hosts.len(), 1);
+    }
+}
@@ -1,8 +1,10 @@
+#[cfg(feature = "animation")]
+use crate::utils::Animation;
 use crate::{
     dbusmenu::{
         AboutToShowFn, AboutToShowGroupFn, DBusMenuBootFn, DBusMenuInstance, DBusMenuItem,
         EventUpdate, IconThemePathFn, MenuBootFn, MenuStatus, MenuStatusFn, MenuUnit, OnClickedFn,
-        RevisionFn, TextDirectionFn,
+        OnClosedFn, OnHoveredFn, OnOpenedFn, PopulateSubMenuFn, RevisionFn, TextDirectionFn,
     },
     status_notifier_item::{
         ActivateFn, AttentionIconNameFn, AttentionIconPixmapFn, AttentionMovieNameFn, CategoryFn,
@@ -11,23 +13,203 @@ use crate::{
         ScrollFn, SecondaryActivateFn, StatusNotifierInstance, StatusNotifierItem, TitleFn,
         ToolTipFn, WindowIdFn,
     },
-    status_notifier_watcher::StatusNotifierWatcherProxy,
-    utils::{Category, IconPixmap, MenuTree, TextDirection, ToolTip},
+    status_notifier_watcher::{StatusNotifierWatcherProxy, WATCHER_SERVICE, is_watcher_missing},
+    utils::{Category, IconBundle, IconPixmap, MenuTree, TextDirection, ToolTip},
 };
-use std::marker::PhantomData;
+use std::any::Any;
+#[cfg(feature = "animation")]
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "animation")]
+use std::time::Duration;
+
+use futures_lite::StreamExt;
+#[cfg(feature = "animation")]
+use zbus::object_server::InterfaceRef;
+use zbus::{
+    connection,
+    fdo::{DBusProxy, RequestNameFlags, RequestNameReply},
+    proxy::CacheProperties,
+    zvariant::{ObjectPath, OwnedValue},
+};
+
+/// Whether the item can currently be seen by the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrayAvailability {
+    /// Registered with a watcher that has a host to show the item.
+    Visible,
+    /// Registered with a watcher, but no host is running.
+    NoHost,
+    /// The watcher went away, the item registers again once one comes back.
+    NoWatcher,
+    /// There was no watcher at startup, the item registers as soon as one appears.
+    ///
+    /// Only reported with [`Tray::with_wait_for_watcher`].
+    Pending,
+}
+
+pub trait TrayAvailabilityFn {
+    fn on_availability_changed(&self, availability: TrayAvailability);
+}
+
+impl<T> TrayAvailabilityFn for T
+where
+    T: Fn(TrayAvailability),
+{
+    fn on_availability_changed(&self, availability: TrayAvailability) {
+        self(availability)
+    }
+}
+
+pub trait TrayFallbackFn {
+    fn on_fallback(&self);
+}
+
+impl<T> TrayFallbackFn for T
+where
+    T: Fn(),
+{
+    fn on_fallback(&self) {
+        self()
+    }
+}
+
+type SharedAvailabilityFn = Arc<dyn TrayAvailabilityFn + Send + Sync>;
+type SharedFallbackFn = Arc<dyn TrayFallbackFn + Send + Sync>;
+
+type SharedNotifier<State> = Arc<dyn StatusNotifierItem<State = State> + Send + Sync>;
+type SharedMenu<State, Message> =
+    Arc<dyn DBusMenuItem<State = State, Message = Message> + Send + Sync>;
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+/// Everything about a tray that is not part of its programs.
+#[derive(Clone)]
+struct TrayConfig {
+    on_availability_changed: SharedAvailabilityFn,
+    on_fallback: SharedFallbackFn,
+    wait_for_watcher: bool,
+    item_path: String,
+    menu_path: String,
+    icon_bundle: Option<Arc<IconBundle>>,
+}
 
-use zbus::connection;
+impl Default for TrayConfig {
+    fn default() -> Self {
+        TrayConfig {
+            on_availability_changed: Arc::new(|_availability: TrayAvailability| {}),
+            on_fallback: Arc::new(|| {}),
+            wait_for_watcher: false,
+            item_path: ITEM_PATH.to_owned(),
+            menu_path: MENU_PATH.to_owned(),
+            icon_bundle: None,
+        }
+    }
+}
 
 pub struct Tray<P: StatusNotifierItem, M: DBusMenuItem> {
     notifier_raw: P,
     menu_raw: M,
+    config: TrayConfig,
 }
 
-pub struct TrayConnection<State, MenuState, Message> {
+/// A running tray, removed from the bus once dropped or [`shut down`].
+///
+/// [`shut down`]: TrayConnection::shutdown
+pub struct TrayConnection<State, MenuState, Message>
+where
+    State: 'static + Send + Sync,
+    Message: 'static + Send + Sync + Clone,
+    MenuState: 'static + Send + Sync,
+{
     conn: zbus::Connection,
-    _state: PhantomData<State>,
-    _menu_state: PhantomData<MenuState>,
-    _message: PhantomData<Message>,
+    /// Whether the connection was made for this tray alone.
+    owns_connection: bool,
+    name: String,
+    availability: Arc<Mutex<TrayAvailability>>,
+    task: Option<zbus::Task<()>>,
+    #[cfg(feature = "animation")]
+    animation: Mutex<AttentionAnimation>,
+    notifier: SharedNotifier<State>,
+    menu: SharedMenu<MenuState, Message>,
+    config: TrayConfig,
+    closed: bool,
+}
+
+/// The animation set with [`TrayConnection::set_attention_animation`], and
+/// the task playing it while the item needs attention.
+#[cfg(feature = "animation")]
+#[derive(Default)]
+struct AttentionAnimation {
+    animation: Option<Animation>,
+    task: Option<zbus::Task<()>>,
+    /// Cleared by the task once it stopped on its own.
+    playing: Arc<AtomicBool>,
+}
+
+impl<State, MenuState, Message> Drop for TrayConnection<State, MenuState, Message>
+where
+    State: 'static + Send + Sync,
+    Message: 'static + Send + Sync + Clone,
+    MenuState: 'static + Send + Sync,
+{
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let conn = self.conn.clone();
+        let name = self.name.clone();
+        let config = self.config.clone();
+        let owns_connection = self.owns_connection;
+        self.conn
+            .executor()
+            .spawn(
+                async move {
+                    let _ =
+                        remove::<State, MenuState, Message>(&conn, &name, &config, owns_connection)
+                            .await;
+                },
+                "tray shutdown",
+            )
+            .detach();
+    }
+}
+
+/// Take a tray off the bus, closing its connection if it was made for it.
+async fn remove<State, MenuState, Message>(
+    conn: &zbus::Connection,
+    name: &str,
+    config: &TrayConfig,
+    owns_connection: bool,
+) -> zbus::Result<()>
+where
+    State: 'static + Send + Sync,
+    Message: 'static + Send + Sync + Clone,
+    MenuState: 'static + Send + Sync,
+{
+    let removed = async {
+        let object_server = conn.object_server();
+        object_server
+            .remove::<StatusNotifierInstance<State>, _>(config.item_path.as_str())
+            .await?;
+        object_server
+            .remove::<DBusMenuInstance<MenuState, Message>, _>(config.menu_path.as_str())
+            .await?;
+        conn.release_name(name).await
+    }
+    .await;
+    // The icons are not left behind, even when the bus already went away
+    let cleaned = match &config.icon_bundle {
+        Some(bundle) => bundle.remove(),
+        None => Ok(()),
+    };
+    removed?;
+    cleaned.map_err(|err| zbus::Error::InputOutput(Arc::new(err)))?;
+    if owns_connection {
+        conn.clone().close().await?;
+    }
+    Ok(())
 }
 
 impl<State, MenuState, Message> TrayConnection<State, MenuState, Message>
@@ -36,6 +218,7 @@ where
     Message: 'static + Send + Sync + Clone,
     MenuState: 'static + Send + Sync,
 {
+    /// Change the state, then tell the hosts about the properties it changed.
     pub async fn update_notify_state<F, R>(&self, f: F) -> zbus::Result<R>
     where
         F: Fn(&mut State) -> R,
@@ -43,10 +226,17 @@ where
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, StatusNotifierInstance<State>>("/StatusNotifierItem")
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
             .await?;
         let mut data = iface_ref.get_mut().await;
-        Ok(f(&mut data.state))
+        let old = data.snapshot();
+        let output = f(&mut data.state);
+        data.notify_changes(&old, iface_ref.signal_emitter())
+            .await?;
+        drop(data);
+        #[cfg(feature = "animation")]
+        self.sync_attention_animation(&iface_ref).await?;
+        Ok(output)
     }
     pub async fn update_menu_state<F, R>(&self, f: F) -> zbus::Result<R>
     where
@@ -55,27 +245,27 @@ where
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, DBusMenuInstance<MenuState, Message>>("/MenuBar")
+            .interface::<_, DBusMenuInstance<MenuState, Message>>(self.config.menu_path.as_str())
             .await?;
         let mut data = iface_ref.get_mut().await;
         Ok(f(&mut data.state))
     }
 
+    /// Replace the menu, telling the hosts only about what changed.
+    ///
+    /// Units are matched by id, so give them a key with [`MenuUnit::with_key`]
+    /// to get property updates instead of a relayout of their parent.
     pub async fn update_full_menu(&self, menu_tree: MenuTree<Message>) -> zbus::Result<()> {
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, DBusMenuInstance<MenuState, Message>>("/MenuBar")
+            .interface::<_, DBusMenuInstance<MenuState, Message>>(self.config.menu_path.as_str())
             .await?;
         let mut data = iface_ref.get_mut().await;
-        data.menu_tree = menu_tree;
-        let _ = DBusMenuInstance::<MenuState, Message>::layout_updated(
-            iface_ref.signal_emitter(),
-            data.program.revision(&data.state),
-            *crate::dbusmenu::Id::MAIN,
-        )
-        .await;
-        Ok(())
+        let changes = data.update_menu_tree(menu_tree);
+        changes
+            .emit::<MenuState, Message>(iface_ref.signal_emitter(), data.revision())
+            .await
     }
 
     pub async fn update_state<F, R>(&self, f: F) -> zbus::Result<R>
@@ -85,26 +275,232 @@ where
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, StatusNotifierInstance<State>>("/StatusNotifierItem")
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
             .await?;
         let menu_iface_ref = self
             .conn
             .object_server()
-            .interface::<_, DBusMenuInstance<MenuState, Message>>("/MenuBar")
+            .interface::<_, DBusMenuInstance<MenuState, Message>>(self.config.menu_path.as_str())
             .await?;
         let mut data = iface_ref.get_mut().await;
         let mut menu_data = menu_iface_ref.get_mut().await;
-        Ok(f(&mut data.state, &mut menu_data.state))
+        let old = data.snapshot();
+        let output = f(&mut data.state, &mut menu_data.state);
+        data.notify_changes(&old, iface_ref.signal_emitter())
+            .await?;
+        drop((data, menu_data));
+        #[cfg(feature = "animation")]
+        self.sync_attention_animation(&iface_ref).await?;
+        Ok(output)
     }
     pub fn unique_name(&self) -> Option<&zbus::names::OwnedUniqueName> {
         self.conn.unique_name()
     }
 
+    /// The `org.kde.StatusNotifierItem-<pid>-<n>` name registered with the watcher.
+    pub fn service_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the item can currently be seen, as last reported to
+    /// [`Tray::with_on_availability_changed`].
+    pub fn availability(&self) -> TrayAvailability {
+        *self.availability.lock().unwrap()
+    }
+
+    /// The name the item is registered with, in the `service/path` notation
+    /// when it is not served at the default path.
+    fn service(&self) -> String {
+        if self.config.item_path == ITEM_PATH {
+            self.name.clone()
+        } else {
+            format!("{}{}", self.name, self.config.item_path)
+        }
+    }
+
+    /// Register the item, then keep it registered whenever a watcher or host
+    /// comes back.
+    async fn watch(&mut self) -> zbus::Result<()> {
+        let service = self.service();
+        let conn = &self.conn;
+
+        // Subscribe before registering, so a watcher restarting in between is not missed
+        let watcher = StatusNotifierWatcherProxy::builder(conn)
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let host_registered = watcher
+            .receive_status_notifier_host_registered()
+            .await?
+            .map(|_| WatcherEvent::HostRegistered);
+        let host_unregistered = watcher
+            .receive_status_notifier_host_unregistered()
+            .await?
+            .map(|_| WatcherEvent::HostUnregistered);
+        let owner_changed = DBusProxy::new(conn)
+            .await?
+            .receive_name_owner_changed_with_args(&[(0, WATCHER_SERVICE)])
+            .await?
+            .filter_map(|signal| {
+                let args = signal.args().ok()?;
+                Some(match args.new_owner().as_ref() {
+                    Some(_) => WatcherEvent::WatcherStarted,
+                    None => WatcherEvent::WatcherStopped,
+                })
+            });
+        let mut events = host_registered.or(host_unregistered).or(owner_changed);
+
+        let on_availability_changed = self.config.on_availability_changed.clone();
+        let on_fallback = self.config.on_fallback.clone();
+        let current = match register(&watcher, &service).await {
+            Ok(current) => current,
+            Err(err) if self.config.wait_for_watcher && is_watcher_missing(&err) => {
+                TrayAvailability::Pending
+            }
+            Err(err) => return Err(err),
+        };
+        *self.availability.lock().unwrap() = current;
+        on_availability_changed.on_availability_changed(current);
+        if current == TrayAvailability::Pending {
+            on_fallback.on_fallback();
+        }
+
+        let task_availability = self.availability.clone();
+        let task = conn.executor().spawn(
+            async move {
+                while let Some(event) = events.next().await {
+                    let previous = *task_availability.lock().unwrap();
+                    let next = match event {
+                        WatcherEvent::WatcherStarted | WatcherEvent::HostRegistered => {
+                            match register(&watcher, &service).await {
+                                Ok(next) => next,
+                                Err(err)
+                                    if previous == TrayAvailability::Pending
+                                        && is_watcher_missing(&err) =>
+                                {
+                                    previous
+                                }
+                                Err(_) => TrayAvailability::NoWatcher,
+                            }
+                        }
+                        WatcherEvent::HostUnregistered => {
+                            if watcher
+                                .is_status_notifier_host_registered()
+                                .await
+                                .unwrap_or(false)
+                            {
+                                TrayAvailability::Visible
+                            } else {
+                                TrayAvailability::NoHost
+                            }
+                        }
+                        WatcherEvent::WatcherStopped if previous == TrayAvailability::Pending => {
+                            previous
+                        }
+                        WatcherEvent::WatcherStopped => TrayAvailability::NoWatcher,
+                    };
+                    if next == previous {
+                        continue;
+                    }
+                    *task_availability.lock().unwrap() = next;
+                    on_availability_changed.on_availability_changed(next);
+                    if next == TrayAvailability::NoWatcher {
+                        on_fallback.on_fallback();
+                    }
+                }
+            },
+            "status notifier item registration",
+        );
+        self.task = Some(task);
+        Ok(())
+    }
+
+    /// Cycle the attention icon through the frames of `animation` while the
+    /// status is [`NotifierStatus::NeedsAttention`], replacing the previous
+    /// animation. `None` goes back to the program's attention icon.
+    ///
+    /// The animation starts and stops as the status is changed through
+    /// [`TrayConnection::update_notify_state`], [`TrayConnection::update_state`]
+    /// or [`TrayConnection::notify_status_changed`]. It also stops by itself
+    /// on the next frame after the status changed some other way.
+    #[cfg(feature = "animation")]
+    pub async fn set_attention_animation(&self, animation: Option<Animation>) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        *self.animation.lock().unwrap() = AttentionAnimation {
+            animation: animation.filter(|animation| !animation.frames().is_empty()),
+            ..Default::default()
+        };
+        self.sync_attention_animation(&iface_ref).await
+    }
+
+    /// Play the attention animation if the item needs attention, and stop it
+    /// otherwise.
+    #[cfg(feature = "animation")]
+    async fn sync_attention_animation(
+        &self,
+        iface_ref: &InterfaceRef<StatusNotifierInstance<State>>,
+    ) -> zbus::Result<()> {
+        let needs_attention = needs_attention(&*iface_ref.get().await);
+        {
+            let mut slot = self.animation.lock().unwrap();
+            if needs_attention {
+                if slot.playing.load(atomic::Ordering::Acquire) {
+                    return Ok(());
+                }
+                if let Some(animation) = slot.animation.clone() {
+                    let playing = Arc::new(AtomicBool::new(true));
+                    let task = self.conn.executor().spawn(
+                        animate(iface_ref.clone(), animation, playing.clone()),
+                        "attention animation",
+                    );
+                    slot.task = Some(task);
+                    slot.playing = playing;
+                    return Ok(());
+                }
+            }
+            slot.task.take();
+            slot.playing.store(false, atomic::Ordering::Release);
+        }
+        iface_ref
+            .get_mut()
+            .await
+            .show_attention_frame(None, iface_ref.signal_emitter())
+            .await
+    }
+
+    /// Remove the item from the bus, so the hosts stop showing it.
+    ///
+    /// The returned tray can be [`Tray::run`] again later, booting a new state.
+    pub async fn shutdown(
+        mut self,
+    ) -> zbus::Result<Tray<SharedNotifier<State>, SharedMenu<MenuState, Message>>> {
+        self.task.take();
+        #[cfg(feature = "animation")]
+        self.animation.lock().unwrap().task.take();
+        remove::<State, MenuState, Message>(
+            &self.conn,
+            &self.name,
+            &self.config,
+            self.owns_connection,
+        )
+        .await?;
+        self.closed = true;
+        Ok(Tray {
+            notifier_raw: self.notifier.clone(),
+            menu_raw: self.menu.clone(),
+            config: self.config.clone(),
+        })
+    }
+
     pub async fn notify_id_changed(&self) -> zbus::Result<()> {
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, StatusNotifierInstance<State>>("/StatusNotifierItem")
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
             .await?;
         let iface = iface_ref.get().await;
         iface.id_changed(iface_ref.signal_emitter()).await
@@ -114,16 +510,73 @@ where
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, StatusNotifierInstance<State>>("/StatusNotifierItem")
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
             .await?;
         StatusNotifierInstance::<State>::new_icon(iface_ref.signal_emitter()).await
     }
 
+    pub async fn notify_attention_icon_changed(&self) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        StatusNotifierInstance::<State>::new_attention_icon(iface_ref.signal_emitter()).await
+    }
+
+    pub async fn notify_overlay_icon_changed(&self) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        StatusNotifierInstance::<State>::new_overlay_icon(iface_ref.signal_emitter()).await
+    }
+
+    pub async fn notify_title_changed(&self) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        let iface = iface_ref.get().await;
+        iface.title_changed(iface_ref.signal_emitter()).await?;
+        StatusNotifierInstance::<State>::new_title(iface_ref.signal_emitter()).await
+    }
+
+    pub async fn notify_tool_tip_changed(&self) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        let iface = iface_ref.get().await;
+        iface.tool_tip_changed(iface_ref.signal_emitter()).await?;
+        StatusNotifierInstance::<State>::new_tool_tip(iface_ref.signal_emitter()).await
+    }
+
+    pub async fn notify_status_changed(&self) -> zbus::Result<()> {
+        let iface_ref = self
+            .conn
+            .object_server()
+            .interface::<_, StatusNotifierInstance<State>>(self.config.item_path.as_str())
+            .await?;
+        let iface = iface_ref.get().await;
+        iface.status_changed(iface_ref.signal_emitter()).await?;
+        let status = iface.program.status(&iface.state)?;
+        StatusNotifierInstance::<State>::new_status(iface_ref.signal_emitter(), status.as_str())
+            .await?;
+        drop(iface);
+        #[cfg(feature = "animation")]
+        self.sync_attention_animation(&iface_ref).await?;
+        Ok(())
+    }
+
     pub async fn notify_layout_changed(&self, revision: u32, parent: i32) -> zbus::Result<()> {
         let iface_ref = self
             .conn
             .object_server()
-            .interface::<_, DBusMenuInstance<MenuState, Message>>("/MenuBar")
+            .interface::<_, DBusMenuInstance<MenuState, Message>>(self.config.menu_path.as_str())
             .await?;
         let _ = DBusMenuInstance::<MenuState, Message>::layout_updated(
             iface_ref.signal_emitter(),
@@ -136,6 +589,99 @@ where
     }
 }
 
+enum WatcherEvent {
+    WatcherStarted,
+    WatcherStopped,
+    HostRegistered,
+    HostUnregistered,
+}
+
+/// Own the first free `org.kde.StatusNotifierItem-<pid>-<n>` name.
+async fn request_item_name(conn: &zbus::Connection) -> zbus::Result<String> {
+    let pid = std::process::id();
+    let mut index = 1;
+    loop {
+        let name = format!("org.kde.StatusNotifierItem-{pid}-{index}");
+        match conn
+            .request_name_with_flags(name.as_str(), RequestNameFlags::DoNotQueue.into())
+            .await
+        {
+            Ok(RequestNameReply::PrimaryOwner) => return Ok(name),
+            // AlreadyOwner too, that name belongs to another tray on this connection
+            Ok(_) | Err(zbus::Error::NameTaken) => index += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Keeps frames without delay from flooding the bus.
+#[cfg(feature = "animation")]
+const ANIMATION_MIN_DELAY: Duration = Duration::from_millis(20);
+
+#[cfg(feature = "animation")]
+fn needs_attention<State: 'static + Send + Sync>(item: &StatusNotifierInstance<State>) -> bool {
+    item.program
+        .status(&item.state)
+        .is_ok_and(|status| status == NotifierStatus::NeedsAttention)
+}
+
+/// Show the frames one after the other until the item no longer needs attention.
+#[cfg(feature = "animation")]
+async fn animate<State: 'static + Send + Sync>(
+    iface_ref: InterfaceRef<StatusNotifierInstance<State>>,
+    animation: Animation,
+    playing: Arc<AtomicBool>,
+) {
+    for frame in animation.frames().iter().cycle() {
+        {
+            let mut item = iface_ref.get_mut().await;
+            if !needs_attention(&item) {
+                playing.store(false, atomic::Ordering::Release);
+                let _ = item
+                    .show_attention_frame(None, iface_ref.signal_emitter())
+                    .await;
+                return;
+            }
+            let _ = item
+                .show_attention_frame(Some(frame.pixmaps.clone()), iface_ref.signal_emitter())
+                .await;
+        }
+        async_io::Timer::after(frame.delay.max(ANIMATION_MIN_DELAY)).await;
+    }
+}
+
+fn already_served(path: &ObjectPath<'_>) -> zbus::Error {
+    zbus::Error::Failure(format!("{path} is already served"))
+}
+
+/// Register the item and tell whether a host is there to show it.
+async fn register(
+    watcher: &StatusNotifierWatcherProxy<'_>,
+    service: &str,
+) -> zbus::Result<TrayAvailability> {
+    watcher.register_status_notifier_item(service).await?;
+    let host_registered = watcher
+        .is_status_notifier_host_registered()
+        .await
+        .unwrap_or(false);
+    Ok(if host_registered {
+        TrayAvailability::Visible
+    } else {
+        TrayAvailability::NoHost
+    })
+}
+
+/// Reuse `program` when it already is the shared one, as for the tray
+/// returned by [`TrayConnection::shutdown`], instead of wrapping it once more
+/// on every run.
+fn share<T: 'static, Shared: 'static>(program: T, wrap: impl FnOnce(T) -> Shared) -> Shared {
+    let mut program = Some(program);
+    if let Some(shared) = (&mut program as &mut dyn Any).downcast_mut::<Option<Shared>>() {
+        return shared.take().expect("the program is taken once");
+    }
+    wrap(program.expect("the program is taken once"))
+}
+
 impl<P: StatusNotifierItem, M: DBusMenuItem> Tray<P, M>
 where
     P::State: 'static + Send + Sync,
@@ -145,37 +691,137 @@ where
     M: Send + Sync + 'static,
 {
     pub async fn run(self) -> zbus::Result<TrayConnection<P::State, M::State, M::Message>> {
-        let state = self.notifier_raw.boot();
+        self.run_with_builder(connection::Builder::session()?).await
+    }
+
+    /// Run the tray on a connection of its own, built from `builder`, for
+    /// instance one made with [`connection::Builder::address`] to use a
+    /// private bus.
+    pub async fn run_with_builder(
+        self,
+        builder: connection::Builder<'_>,
+    ) -> zbus::Result<TrayConnection<P::State, M::State, M::Message>> {
+        let conn = builder.build().await?;
+        self.serve(conn, true).await
+    }
+
+    /// Run the tray on an existing connection, shared with other trays or
+    /// services. Give each tray on it its own [`Tray::with_object_paths`].
+    pub async fn run_on(
+        self,
+        conn: &zbus::Connection,
+    ) -> zbus::Result<TrayConnection<P::State, M::State, M::Message>> {
+        self.serve(conn.clone(), false).await
+    }
+
+    async fn serve(
+        self,
+        conn: zbus::Connection,
+        owns_connection: bool,
+    ) -> zbus::Result<TrayConnection<P::State, M::State, M::Message>> {
+        let item_path = ObjectPath::try_from(self.config.item_path.as_str())?;
+        let menu_path = ObjectPath::try_from(self.config.menu_path.as_str())?;
+
+        let notifier = share::<_, SharedNotifier<P::State>>(self.notifier_raw, |raw| Arc::new(raw));
+        let menu_program =
+            share::<_, SharedMenu<M::State, M::Message>>(self.menu_raw, |raw| Arc::new(raw));
 
         let instance = StatusNotifierInstance {
-            program: Box::new(self.notifier_raw),
-            state,
+            program: notifier.clone(),
+            state: notifier.boot(),
+            menu_path: menu_path.clone().into(),
+            attention_frame: None,
         };
 
-        let menu_state = self.menu_raw.boot();
-        let menu = self.menu_raw.menu();
-        let instance_menu = DBusMenuInstance {
-            program: Box::new(self.menu_raw),
-            state: menu_state,
-            menu_tree: menu,
-        };
-        let conn = connection::Builder::session()?
-            .serve_at("/StatusNotifierItem", instance)?
-            .serve_at("/MenuBar", instance_menu)?
-            .build()
-            .await?;
-        let service = conn.unique_name().unwrap().to_string();
-        StatusNotifierWatcherProxy::builder(&conn)
-            .build()
-            .await?
-            .register_status_notifier_item(&service)
-            .await?;
-        Ok(TrayConnection {
+        let menu_state = menu_program.boot();
+        let menu = menu_program.menu();
+        let instance_menu = DBusMenuInstance::new(menu_program.clone(), menu_state, menu);
+
+        let name = request_item_name(&conn).await?;
+        let object_server = conn.object_server();
+        if !object_server.at(&item_path, instance).await? {
+            let _ = conn.release_name(name.as_str()).await;
+            return Err(already_served(&item_path));
+        }
+        if !object_server.at(&menu_path, instance_menu).await? {
+            let _ = object_server
+                .remove::<StatusNotifierInstance<P::State>, _>(&item_path)
+                .await;
+            let _ = conn.release_name(name.as_str()).await;
+            return Err(already_served(&menu_path));
+        }
+
+        // From here on, dropping it takes the tray off the bus again
+        let mut tray_connection = TrayConnection {
             conn,
-            _state: PhantomData,
-            _menu_state: PhantomData,
-            _message: PhantomData,
-        })
+            owns_connection,
+            name,
+            availability: Arc::new(Mutex::new(TrayAvailability::Pending)),
+            task: None,
+            #[cfg(feature = "animation")]
+            animation: Mutex::default(),
+            notifier,
+            menu: menu_program,
+            config: self.config,
+            closed: false,
+        };
+        if let Some(bundle) = &tray_connection.config.icon_bundle {
+            bundle
+                .write()
+                .map_err(|err| zbus::Error::InputOutput(Arc::new(err)))?;
+        }
+        tray_connection.watch().await?;
+        Ok(tray_connection)
+    }
+    /// Get told whenever the item appears or disappears, such as when the
+    /// panel restarts. The item registers again by itself either way.
+    pub fn with_on_availability_changed(
+        self,
+        f: impl TrayAvailabilityFn + Send + Sync + 'static,
+    ) -> Tray<P, M> {
+        let mut config = self.config;
+        config.on_availability_changed = Arc::new(f);
+        Tray { config, ..self }
+    }
+    /// Keep serving the item when no watcher is running yet, reporting
+    /// [`TrayAvailability::Pending`] and registering as soon as one appears,
+    /// instead of failing [`Tray::run`].
+    pub fn with_wait_for_watcher(self, wait_for_watcher: bool) -> Tray<P, M> {
+        let mut config = self.config;
+        config.wait_for_watcher = wait_for_watcher;
+        Tray { config, ..self }
+    }
+    /// Called whenever there is no watcher to show the item, either at
+    /// startup with [`Tray::with_wait_for_watcher`] or when it goes away,
+    /// so the app can offer another way in, like showing its window.
+    pub fn with_fallback(self, f: impl TrayFallbackFn + Send + Sync + 'static) -> Tray<P, M> {
+        let mut config = self.config;
+        config.on_fallback = Arc::new(f);
+        Tray { config, ..self }
+    }
+    /// Serve the item and its menu somewhere else than `/StatusNotifierItem`
+    /// and `/MenuBar`, to run several trays on one connection.
+    pub fn with_object_paths(self, item_path: &str, menu_path: &str) -> Tray<P, M> {
+        let mut config = self.config;
+        config.item_path = item_path.to_owned();
+        config.menu_path = menu_path.to_owned();
+        Tray { config, ..self }
+    }
+    /// Serve the icons of `bundle` as the icon theme of the item and its menu,
+    /// to use them by name without installing them.
+    pub fn with_icon_bundle(
+        self,
+        bundle: IconBundle,
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
+        let path = bundle.theme_path().to_string_lossy().into_owned();
+        let mut config = self.config;
+        config.icon_bundle = Some(Arc::new(bundle));
+        Tray { config, ..self }
+            .with_tray_icon_theme_path(path.clone())
+            .with_menu_icon_theme_path(vec![path])
     }
     pub fn with_tool_tip(
         self,
@@ -187,6 +833,7 @@ where
         Tray {
             notifier_raw: with_tool_tip(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_tray_icon_theme_path(
@@ -199,6 +846,7 @@ where
         Tray {
             notifier_raw: with_tray_icon_theme_path(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_icon_name(
@@ -211,6 +859,7 @@ where
         Tray {
             notifier_raw: with_icon_name(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_icon_pixmap(
@@ -223,6 +872,7 @@ where
         Tray {
             notifier_raw: with_icon_pixmap(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_attention_icon_name(
@@ -235,6 +885,7 @@ where
         Tray {
             notifier_raw: with_attention_icon_name(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_attention_icon_pixmap(
@@ -247,6 +898,7 @@ where
         Tray {
             notifier_raw: with_attention_icon_pixmap(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_attention_movie_name(
@@ -259,6 +911,7 @@ where
         Tray {
             notifier_raw: with_attention_movie_name(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_overlay_icon_name(
@@ -271,6 +924,7 @@ where
         Tray {
             notifier_raw: with_overlay_icon_name(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_overlay_icon_pixmap(
@@ -283,6 +937,7 @@ where
         Tray {
             notifier_raw: with_overlay_icon_pixmap(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_item_is_menu(
@@ -295,6 +950,7 @@ where
         Tray {
             notifier_raw: with_item_is_menu(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
 
@@ -308,6 +964,7 @@ where
         Tray {
             notifier_raw: with_scroll(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_activate(
@@ -320,6 +977,7 @@ where
         Tray {
             notifier_raw: with_activate(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_category(
@@ -332,16 +990,21 @@ where
         Tray {
             notifier_raw: with_category(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
 
     pub fn with_tray_status(
         self,
         f: impl NotifierStatusFn<P::State>,
-    ) -> Tray<impl StatusNotifierItem<State = P::State>, impl DBusMenuItem<State = M::State>> {
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
         Tray {
             notifier_raw: with_tray_status(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_window_id(
@@ -354,6 +1017,7 @@ where
         Tray {
             notifier_raw: with_window_id(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
     pub fn with_secondary_activate(
@@ -366,6 +1030,7 @@ where
         Tray {
             notifier_raw: with_secondary_activate(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
 
@@ -379,6 +1044,7 @@ where
         Tray {
             notifier_raw: with_context_menu(self.notifier_raw, f),
             menu_raw: self.menu_raw,
+            config: self.config,
         }
     }
 
@@ -392,6 +1058,7 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_menu_status(self.menu_raw, f),
+            config: self.config,
         }
     }
 
@@ -405,6 +1072,63 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_on_clicked(self.menu_raw, f),
+            config: self.config,
+        }
+    }
+
+    pub fn with_populate_sub_menu(
+        self,
+        f: impl PopulateSubMenuFn<M::State, M::Message>,
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
+        Tray {
+            notifier_raw: self.notifier_raw,
+            menu_raw: with_populate_sub_menu(self.menu_raw, f),
+            config: self.config,
+        }
+    }
+
+    pub fn with_on_opened(
+        self,
+        f: impl OnOpenedFn<M::State>,
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
+        Tray {
+            notifier_raw: self.notifier_raw,
+            menu_raw: with_on_opened(self.menu_raw, f),
+            config: self.config,
+        }
+    }
+
+    pub fn with_on_closed(
+        self,
+        f: impl OnClosedFn<M::State>,
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
+        Tray {
+            notifier_raw: self.notifier_raw,
+            menu_raw: with_on_closed(self.menu_raw, f),
+            config: self.config,
+        }
+    }
+
+    pub fn with_on_hovered(
+        self,
+        f: impl OnHoveredFn<M::State>,
+    ) -> Tray<
+        impl StatusNotifierItem<State = P::State>,
+        impl DBusMenuItem<State = M::State, Message = M::Message>,
+    > {
+        Tray {
+            notifier_raw: self.notifier_raw,
+            menu_raw: with_on_hovered(self.menu_raw, f),
+            config: self.config,
         }
     }
 
@@ -418,6 +1142,7 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_text_direction(self.menu_raw, f),
+            config: self.config,
         }
     }
 
@@ -431,6 +1156,7 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_menu_icon_theme_path(self.menu_raw, f),
+            config: self.config,
         }
     }
     pub fn with_about_to_show(
@@ -443,6 +1169,7 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_about_to_show(self.menu_raw, f),
+            config: self.config,
         }
     }
     pub fn with_about_to_show_group(
@@ -455,6 +1182,7 @@ where
         Tray {
             notifier_raw: self.notifier_raw,
             menu_raw: with_about_to_show_group(self.menu_raw, f),
+            config: self.config,
         }
     }
 }
@@ -2033,6 +2761,40 @@ fn with_menu_status<M: DBusMenuItem>(
         ) -> EventUpdate {
             self.program.on_clicked(state, button, message, timestamp)
         }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
         fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.program.text_direction(state)
         }
@@ -2095,6 +2857,40 @@ fn with_on_clicked<M: DBusMenuItem>(
             self.on_clicked
                 .on_clicked(state, button, message, timestamp)
         }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
         fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.program.text_direction(state)
         }
@@ -2108,18 +2904,18 @@ fn with_on_clicked<M: DBusMenuItem>(
     }
 }
 
-fn with_text_direction<M: DBusMenuItem>(
+fn with_populate_sub_menu<M: DBusMenuItem>(
     program: M,
-    text_direction: impl TextDirectionFn<M::State>,
+    populate_sub_menu: impl PopulateSubMenuFn<M::State, M::Message>,
 ) -> impl DBusMenuItem<State = M::State, Message = M::Message> {
-    struct WithTextDirection<M, TextDirectionFn> {
+    struct WithPopulateSubMenu<M, PopulateSubMenuFn> {
         program: M,
-        text_direction: TextDirectionFn,
+        populate_sub_menu: PopulateSubMenuFn,
     }
 
-    impl<M: DBusMenuItem, TextDirectionFn> DBusMenuItem for WithTextDirection<M, TextDirectionFn>
+    impl<M: DBusMenuItem, PopulateSubMenuFn> DBusMenuItem for WithPopulateSubMenu<M, PopulateSubMenuFn>
     where
-        TextDirectionFn: self::TextDirectionFn<M::State>,
+        PopulateSubMenuFn: self::PopulateSubMenuFn<M::State, M::Message>,
     {
         type State = M::State;
         type Message = M::Message;
@@ -2157,7 +2953,419 @@ fn with_text_direction<M: DBusMenuItem>(
         ) -> EventUpdate {
             self.program.on_clicked(state, button, message, timestamp)
         }
-        fn text_direction(&self, state: &Self::State) -> TextDirection {
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.populate_sub_menu.populate_sub_menu(state, message)
+        }
+        fn text_direction(&self, state: &Self::State) -> TextDirection {
+            self.program.text_direction(state)
+        }
+        fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
+            self.program.icon_theme_path(state)
+        }
+    }
+    WithPopulateSubMenu {
+        program,
+        populate_sub_menu,
+    }
+}
+
+fn with_on_opened<M: DBusMenuItem>(
+    program: M,
+    on_opened: impl OnOpenedFn<M::State>,
+) -> impl DBusMenuItem<State = M::State, Message = M::Message> {
+    struct WithOnOpened<M, OnOpenedFn> {
+        program: M,
+        on_opened: OnOpenedFn,
+    }
+
+    impl<M: DBusMenuItem, OnOpenedFn> DBusMenuItem for WithOnOpened<M, OnOpenedFn>
+    where
+        OnOpenedFn: self::OnOpenedFn<M::State>,
+    {
+        type State = M::State;
+        type Message = M::Message;
+
+        fn boot(&self) -> Self::State {
+            self.program.boot()
+        }
+        fn revision(&self, state: &Self::State) -> u32 {
+            self.program.revision(state)
+        }
+
+        fn menu(&self) -> MenuTree<M::Message> {
+            self.program.menu()
+        }
+        fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
+            self.program.about_to_show(state, id)
+        }
+        fn about_to_show_group(
+            &self,
+            state: &mut Self::State,
+            ids: Vec<i32>,
+        ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+            self.program.about_to_show_group(state, ids)
+        }
+        fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
+            self.program.status(state)
+        }
+
+        fn on_clicked(
+            &self,
+            state: &mut Self::State,
+            button: &mut MenuUnit<Self::Message>,
+            message: Self::Message,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_clicked(state, button, message, timestamp)
+        }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.on_opened.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
+        fn text_direction(&self, state: &Self::State) -> TextDirection {
+            self.program.text_direction(state)
+        }
+        fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
+            self.program.icon_theme_path(state)
+        }
+    }
+    WithOnOpened { program, on_opened }
+}
+
+fn with_on_closed<M: DBusMenuItem>(
+    program: M,
+    on_closed: impl OnClosedFn<M::State>,
+) -> impl DBusMenuItem<State = M::State, Message = M::Message> {
+    struct WithOnClosed<M, OnClosedFn> {
+        program: M,
+        on_closed: OnClosedFn,
+    }
+
+    impl<M: DBusMenuItem, OnClosedFn> DBusMenuItem for WithOnClosed<M, OnClosedFn>
+    where
+        OnClosedFn: self::OnClosedFn<M::State>,
+    {
+        type State = M::State;
+        type Message = M::Message;
+
+        fn boot(&self) -> Self::State {
+            self.program.boot()
+        }
+        fn revision(&self, state: &Self::State) -> u32 {
+            self.program.revision(state)
+        }
+
+        fn menu(&self) -> MenuTree<M::Message> {
+            self.program.menu()
+        }
+        fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
+            self.program.about_to_show(state, id)
+        }
+        fn about_to_show_group(
+            &self,
+            state: &mut Self::State,
+            ids: Vec<i32>,
+        ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+            self.program.about_to_show_group(state, ids)
+        }
+        fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
+            self.program.status(state)
+        }
+
+        fn on_clicked(
+            &self,
+            state: &mut Self::State,
+            button: &mut MenuUnit<Self::Message>,
+            message: Self::Message,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_clicked(state, button, message, timestamp)
+        }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.on_closed.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
+        fn text_direction(&self, state: &Self::State) -> TextDirection {
+            self.program.text_direction(state)
+        }
+        fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
+            self.program.icon_theme_path(state)
+        }
+    }
+    WithOnClosed { program, on_closed }
+}
+
+fn with_on_hovered<M: DBusMenuItem>(
+    program: M,
+    on_hovered: impl OnHoveredFn<M::State>,
+) -> impl DBusMenuItem<State = M::State, Message = M::Message> {
+    struct WithOnHovered<M, OnHoveredFn> {
+        program: M,
+        on_hovered: OnHoveredFn,
+    }
+
+    impl<M: DBusMenuItem, OnHoveredFn> DBusMenuItem for WithOnHovered<M, OnHoveredFn>
+    where
+        OnHoveredFn: self::OnHoveredFn<M::State>,
+    {
+        type State = M::State;
+        type Message = M::Message;
+
+        fn boot(&self) -> Self::State {
+            self.program.boot()
+        }
+        fn revision(&self, state: &Self::State) -> u32 {
+            self.program.revision(state)
+        }
+
+        fn menu(&self) -> MenuTree<M::Message> {
+            self.program.menu()
+        }
+        fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
+            self.program.about_to_show(state, id)
+        }
+        fn about_to_show_group(
+            &self,
+            state: &mut Self::State,
+            ids: Vec<i32>,
+        ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+            self.program.about_to_show_group(state, ids)
+        }
+        fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
+            self.program.status(state)
+        }
+
+        fn on_clicked(
+            &self,
+            state: &mut Self::State,
+            button: &mut MenuUnit<Self::Message>,
+            message: Self::Message,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_clicked(state, button, message, timestamp)
+        }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.on_hovered.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
+        fn text_direction(&self, state: &Self::State) -> TextDirection {
+            self.program.text_direction(state)
+        }
+        fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
+            self.program.icon_theme_path(state)
+        }
+    }
+    WithOnHovered {
+        program,
+        on_hovered,
+    }
+}
+
+fn with_text_direction<M: DBusMenuItem>(
+    program: M,
+    text_direction: impl TextDirectionFn<M::State>,
+) -> impl DBusMenuItem<State = M::State, Message = M::Message> {
+    struct WithTextDirection<M, TextDirectionFn> {
+        program: M,
+        text_direction: TextDirectionFn,
+    }
+
+    impl<M: DBusMenuItem, TextDirectionFn> DBusMenuItem for WithTextDirection<M, TextDirectionFn>
+    where
+        TextDirectionFn: self::TextDirectionFn<M::State>,
+    {
+        type State = M::State;
+        type Message = M::Message;
+
+        fn boot(&self) -> Self::State {
+            self.program.boot()
+        }
+        fn revision(&self, state: &Self::State) -> u32 {
+            self.program.revision(state)
+        }
+
+        fn menu(&self) -> MenuTree<M::Message> {
+            self.program.menu()
+        }
+        fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
+            self.program.about_to_show(state, id)
+        }
+        fn about_to_show_group(
+            &self,
+            state: &mut Self::State,
+            ids: Vec<i32>,
+        ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+            self.program.about_to_show_group(state, ids)
+        }
+        fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
+            self.program.status(state)
+        }
+
+        fn on_clicked(
+            &self,
+            state: &mut Self::State,
+            button: &mut MenuUnit<Self::Message>,
+            message: Self::Message,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_clicked(state, button, message, timestamp)
+        }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
+        fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.text_direction.text_direction(state)
         }
         fn icon_theme_path(&self, state: &Self::State) -> Vec<String> {
@@ -2219,6 +3427,40 @@ fn with_menu_icon_theme_path<M: DBusMenuItem>(
         ) -> EventUpdate {
             self.program.on_clicked(state, button, message, timestamp)
         }
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
         fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.program.text_direction(state)
         }
@@ -2260,12 +3502,19 @@ fn with_about_to_show<M: DBusMenuItem>(
         fn about_to_show(&self, state: &mut Self::State, id: i32) -> zbus::fdo::Result<bool> {
             Ok(self.about_to_show.about_to_show(state, id))
         }
+        // The group goes through the same callback, one id at a time, and
+        // the ids it needs no update for through any earlier group callback
         fn about_to_show_group(
             &self,
             state: &mut Self::State,
             ids: Vec<i32>,
         ) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
-            self.program.about_to_show_group(state, ids)
+            let (mut updates_needed, rest): (Vec<i32>, Vec<i32>) = ids
+                .into_iter()
+                .partition(|id| self.about_to_show.about_to_show(state, *id));
+            let (mut forwarded, id_errors) = self.program.about_to_show_group(state, rest)?;
+            updates_needed.append(&mut forwarded);
+            Ok((updates_needed, id_errors))
         }
         fn status(&self, state: &Self::State) -> zbus::fdo::Result<MenuStatus> {
             self.program.status(state)
@@ -2281,6 +3530,40 @@ fn with_about_to_show<M: DBusMenuItem>(
             self.program.on_clicked(state, button, message, timestamp)
         }
 
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
         fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.program.text_direction(state)
         }
@@ -2343,6 +3626,40 @@ fn with_about_to_show_group<M: DBusMenuItem>(
             self.program.on_clicked(state, button, message, timestamp)
         }
 
+        fn on_opened(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_opened(state, id, data, timestamp)
+        }
+        fn on_closed(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_closed(state, id, data, timestamp)
+        }
+        fn on_hovered(
+            &self,
+            state: &mut Self::State,
+            id: i32,
+            data: &OwnedValue,
+            timestamp: u32,
+        ) -> EventUpdate {
+            self.program.on_hovered(state, id, data, timestamp)
+        }
+        fn populate_sub_menu(
+            &self,
+            state: &mut Self::State,
+            message: Self::Message,
+        ) -> Vec<MenuUnit<Self::Message>> {
+            self.program.populate_sub_menu(state, message)
+        }
         fn text_direction(&self, state: &Self::State) -> TextDirection {
             self.program.text_direction(state)
         }
@@ -2440,5 +3757,6 @@ where
             _state: PhantomData,
             _message: PhantomData,
         },
+        config: TrayConfig::default(),
     }
 }